@@ -0,0 +1,466 @@
+use osmpbfreader::fileformat::{Blob, BlobHeader};
+use osmpbfreader::osmformat::{HeaderBlock, PrimitiveBlock};
+use osmpbfreader::{blocks, primitive_block_from_blob, Node, Relation, Way};
+use protobuf::Message;
+use rayon::prelude::*;
+
+use flate2::read::ZlibDecoder;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::ops::Range;
+use std::time::Instant;
+
+// optional feature of the header: all nodes come first, then all ways, then all relations, each ordered by id
+pub const SORTED_FEATURE: &str = "Sort.Type_then_ID";
+// number of blobs held in memory at once; they are decoded in parallel and dropped once their objects are filtered
+const BLOBS_PER_BATCH: usize = 64;
+// limits of the file format specification
+const MAX_BLOB_HEADER_SIZE: u32 = 64 * 1024;
+const MAX_BLOB_SIZE: usize = 32 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Primitive {
+    Node,
+    Way,
+    Relation,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct BlobLocation {
+    offset: u64,
+    size: usize,
+}
+
+// the data blobs of a pbf file and which of them contain nodes, ways, and relations
+pub struct BlockIndex {
+    filename: String,
+    blobs: Vec<BlobLocation>,
+    nodes: Range<usize>,
+    ways: Range<usize>,
+    relations: Range<usize>,
+    optional_features: Vec<String>,
+}
+
+impl BlockIndex {
+    pub fn new(filename: &str) -> Result<BlockIndex> {
+        let now = Instant::now();
+        println!("indexing blocks...");
+
+        let mut reader = BufReader::new(File::open(filename)?);
+        let (blobs, optional_features) = sweep(&mut reader)?;
+        let sorted = optional_features.iter().any(|f| f == SORTED_FEATURE);
+        let all = 0..blobs.len();
+        let mut index = BlockIndex {
+            filename: filename.to_string(),
+            blobs,
+            nodes: all.clone(),
+            ways: all.clone(),
+            relations: all,
+            optional_features,
+        };
+
+        if sorted {
+            index.narrow_ranges(&mut reader)?;
+        } else {
+            println!("the file is not sorted by type, every phase reads all blocks");
+        }
+
+        println!(
+            "finished indexing {} blocks ({} with nodes, {} with ways, {} with relations)! {}s",
+            index.blobs.len(),
+            index.nodes.len(),
+            index.ways.len(),
+            index.relations.len(),
+            now.elapsed().as_secs()
+        );
+        Ok(index)
+    }
+
+    // the optional features declared in the header of the file
+    pub fn optional_features(&self) -> &[String] {
+        &self.optional_features
+    }
+
+    pub fn nodes<T: Send>(&self, filter_map: impl Fn(Node) -> Option<T> + Sync) -> Result<Vec<T>> {
+        let mut result = Vec::new();
+        self.for_each_node(filter_map, |node| {
+            result.push(node);
+            Ok(())
+        })?;
+        Ok(result)
+    }
+
+    pub fn ways<T: Send>(&self, filter_map: impl Fn(Way) -> Option<T> + Sync) -> Result<Vec<T>> {
+        let mut result = Vec::new();
+        self.for_each_way(filter_map, |way| {
+            result.push(way);
+            Ok(())
+        })?;
+        Ok(result)
+    }
+
+    pub fn relations<T: Send>(&self, filter_map: impl Fn(Relation) -> Option<T> + Sync) -> Result<Vec<T>> {
+        let mut result = Vec::new();
+        self.for_each_relation(filter_map, |relation| {
+            result.push(relation);
+            Ok(())
+        })?;
+        Ok(result)
+    }
+
+    // the filtered objects are handed to consume in the order of the file, without collecting them first
+    pub fn for_each_node<T: Send>(
+        &self,
+        filter_map: impl Fn(Node) -> Option<T> + Sync,
+        consume: impl FnMut(T) -> Result<()>,
+    ) -> Result<()> {
+        self.decode(
+            self.nodes.clone(),
+            |block| blocks::nodes(block).filter_map(&filter_map).collect(),
+            consume,
+        )
+    }
+
+    pub fn for_each_way<T: Send>(
+        &self,
+        filter_map: impl Fn(Way) -> Option<T> + Sync,
+        consume: impl FnMut(T) -> Result<()>,
+    ) -> Result<()> {
+        self.decode(
+            self.ways.clone(),
+            |block| blocks::ways(block).filter_map(&filter_map).collect(),
+            consume,
+        )
+    }
+
+    pub fn for_each_relation<T: Send>(
+        &self,
+        filter_map: impl Fn(Relation) -> Option<T> + Sync,
+        consume: impl FnMut(T) -> Result<()>,
+    ) -> Result<()> {
+        self.decode(
+            self.relations.clone(),
+            |block| blocks::relations(block).filter_map(&filter_map).collect(),
+            consume,
+        )
+    }
+
+    fn decode<T: Send>(
+        &self,
+        range: Range<usize>,
+        objects_of: impl Fn(&PrimitiveBlock) -> Vec<T> + Sync,
+        mut consume: impl FnMut(T) -> Result<()>,
+    ) -> Result<()> {
+        /*
+            the blobs are read one batch after another, and the blobs of a batch are decoded in parallel;
+            only the filtered objects of the current batch are kept, so the memory needed does not depend on the size of the file
+        */
+        let mut reader = BufReader::new(File::open(&self.filename)?);
+
+        for batch in self.blobs[range].chunks(BLOBS_PER_BATCH) {
+            let raw_blobs = batch
+                .iter()
+                .map(|location| read_blob(&mut reader, location))
+                .collect::<Result<Vec<Blob>>>()?;
+            let objects = raw_blobs
+                .par_iter()
+                .map(|blob| to_primitive_block(blob).map(|block| objects_of(&block)))
+                .collect::<Result<Vec<Vec<T>>>>()?;
+            for object in objects.into_iter().flatten() {
+                consume(object)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn narrow_ranges(&mut self, reader: &mut BufReader<File>) -> Result<()> {
+        /*
+            in a sorted file the types of the blobs only increase, so the first blob of each type is found
+            with a binary search that decodes only a few blobs; a blob at a transition may contain
+            several types and then belongs to several ranges
+        */
+        let mut types: HashMap<usize, (Primitive, Primitive)> = HashMap::new();
+        let mut first = |predicate: &dyn Fn((Primitive, Primitive)) -> bool| -> Result<usize> {
+            let (mut low, mut high) = (0, self.blobs.len());
+            while low < high {
+                let middle = (low + high) / 2;
+                let blob_types = match types.get(&middle) {
+                    Some(blob_types) => *blob_types,
+                    None => {
+                        let blob_types =
+                            primitive_types(&to_primitive_block(&read_blob(reader, &self.blobs[middle])?)?);
+                        types.insert(middle, blob_types);
+                        blob_types
+                    }
+                };
+                if predicate(blob_types) {
+                    high = middle;
+                } else {
+                    low = middle + 1;
+                }
+            }
+            Ok(low)
+        };
+
+        let nodes_end = first(&|(min, _)| min > Primitive::Node)?;
+        let ways_start = first(&|(_, max)| max >= Primitive::Way)?;
+        let ways_end = first(&|(min, _)| min > Primitive::Way)?;
+        let relations_start = first(&|(_, max)| max >= Primitive::Relation)?;
+
+        self.nodes = 0..nodes_end;
+        self.ways = ways_start..ways_end;
+        self.relations = relations_start..self.blobs.len();
+        Ok(())
+    }
+}
+
+fn sweep(reader: &mut BufReader<File>) -> Result<(Vec<BlobLocation>, Vec<String>)> {
+    /*
+        only the blob headers are read: the size of the header, the header with the type and size of the blob,
+        and then the blob itself is skipped; the OSMHeader blob is decoded for its optional features, e.g., whether the file is sorted
+    */
+    let mut blobs = Vec::new();
+    let mut optional_features = Vec::new();
+    let length = reader.get_ref().metadata()?.len();
+    let mut offset = 0;
+
+    while offset < length {
+        let mut header_size = [0; 4];
+        reader.read_exact(&mut header_size)?;
+        let header_size = u32::from_be_bytes(header_size);
+        if header_size > MAX_BLOB_HEADER_SIZE {
+            return Err(invalid_data(format!("blob header at byte {} is too big", offset)));
+        }
+
+        let mut header_bytes = vec![0; header_size as usize];
+        reader.read_exact(&mut header_bytes)?;
+        let header = BlobHeader::parse_from_bytes(&header_bytes).map_err(|e| invalid_data(e.to_string()))?;
+        let location = BlobLocation {
+            offset: offset + 4 + header_size as u64,
+            size: header.get_datasize() as usize,
+        };
+        if location.size > MAX_BLOB_SIZE {
+            return Err(invalid_data(format!("blob at byte {} is too big", location.offset)));
+        }
+        if location.offset + location.size as u64 > length {
+            return Err(invalid_data(format!("blob at byte {} is truncated", location.offset)));
+        }
+
+        match header.get_field_type() {
+            "OSMHeader" => {
+                let header_block = to_header_block(&read_blob(reader, &location)?)?;
+                optional_features = header_block.get_optional_features().to_vec();
+            }
+            "OSMData" => {
+                blobs.push(location);
+                reader.seek_relative(location.size as i64)?;
+            }
+            _ => reader.seek_relative(location.size as i64)?,
+        }
+        offset = location.offset + location.size as u64;
+    }
+
+    Ok((blobs, optional_features))
+}
+
+fn read_blob(reader: &mut BufReader<File>, location: &BlobLocation) -> Result<Blob> {
+    let mut bytes = vec![0; location.size];
+    reader.seek(SeekFrom::Start(location.offset))?;
+    reader.read_exact(&mut bytes)?;
+    Blob::parse_from_bytes(&bytes).map_err(|e| invalid_data(e.to_string()))
+}
+
+fn to_primitive_block(blob: &Blob) -> Result<PrimitiveBlock> {
+    primitive_block_from_blob(blob).map_err(|e| invalid_data(e.to_string()))
+}
+
+fn to_header_block(blob: &Blob) -> Result<HeaderBlock> {
+    let result = if blob.has_raw() {
+        HeaderBlock::parse_from_bytes(blob.get_raw())
+    } else if blob.has_zlib_data() {
+        HeaderBlock::parse_from_reader(&mut ZlibDecoder::new(blob.get_zlib_data()))
+    } else {
+        return Err(invalid_data(String::from("unsupported compression of the header blob")));
+    };
+    result.map_err(|e| invalid_data(e.to_string()))
+}
+
+// the smallest and biggest type in the block; an empty block counts as containing all types, so it is never skipped
+fn primitive_types(block: &PrimitiveBlock) -> (Primitive, Primitive) {
+    let types: Vec<Primitive> = block
+        .get_primitivegroup()
+        .iter()
+        .flat_map(|group| {
+            vec![
+                (!group.get_dense().get_id().is_empty() || !group.get_nodes().is_empty()).then_some(Primitive::Node),
+                (!group.get_ways().is_empty()).then_some(Primitive::Way),
+                (!group.get_relations().is_empty()).then_some(Primitive::Relation),
+            ]
+        })
+        .flatten()
+        .collect();
+
+    (
+        types.iter().min().copied().unwrap_or(Primitive::Node),
+        types.iter().max().copied().unwrap_or(Primitive::Relation),
+    )
+}
+
+fn invalid_data(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::pbf_writer::{write_blob, PbfWriter};
+    use osmpbfreader::osmformat::{PrimitiveGroup, Way as PbfWay};
+    use osmpbfreader::{NodeId, RelationId, Tags, WayId};
+    use std::io::{BufWriter, Write};
+
+    fn temporary_filename(name: &str) -> String {
+        let filename = format!("osm_extract_polygon_{}_{}.osm.pbf", name, std::process::id());
+        std::env::temp_dir().join(filename).to_string_lossy().to_string()
+    }
+
+    fn create_node(id: i64) -> Node {
+        Node {
+            id: NodeId(id),
+            tags: Tags::new(),
+            decimicro_lat: id as i32,
+            decimicro_lon: -id as i32,
+        }
+    }
+
+    fn create_way(id: i64) -> Way {
+        Way {
+            id: WayId(id),
+            tags: Tags::new(),
+            nodes: vec![NodeId(1), NodeId(id)],
+        }
+    }
+
+    fn create_relation(id: i64) -> Relation {
+        Relation {
+            id: RelationId(id),
+            tags: Tags::new(),
+            refs: Vec::new(),
+        }
+    }
+
+    fn read_ids(index: &BlockIndex) -> (Vec<i64>, Vec<i64>, Vec<i64>) {
+        let sorted = |mut ids: Vec<i64>| {
+            ids.sort_unstable();
+            ids
+        };
+        (
+            sorted(index.nodes(|node| Some(node.id.0)).unwrap()),
+            sorted(index.ways(|way| Some(way.id.0)).unwrap()),
+            sorted(index.relations(|relation| Some(relation.id.0)).unwrap()),
+        )
+    }
+
+    fn create_way_and_relation_block() -> PrimitiveBlock {
+        // the writer puts every type into a block of its own, so the groups of two blocks are merged
+        let filename = temporary_filename("parts");
+        let mut writer = PbfWriter::new(File::create(&filename).unwrap(), None).unwrap();
+        writer.write_way(&create_way(3)).unwrap();
+        writer.write_relation(&create_relation(1)).unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = BufReader::new(File::open(&filename).unwrap());
+        let (locations, _) = sweep(&mut reader).unwrap();
+        let mut blocks: Vec<PrimitiveBlock> = locations
+            .iter()
+            .map(|location| to_primitive_block(&read_blob(&mut reader, location).unwrap()).unwrap())
+            .collect();
+        std::fs::remove_file(&filename).unwrap();
+
+        let relations = blocks.pop().unwrap();
+        let mut block = blocks.pop().unwrap();
+        block
+            .mut_primitivegroup()
+            .push(relations.get_primitivegroup()[0].clone());
+        block
+    }
+
+    #[test]
+    fn test_sorted_file_narrows_the_blocks_of_every_type() {
+        // blocks: nodes 1-8000, node 8001, ways 1-2, way 3 and relation 1
+        let filename = temporary_filename("sorted");
+        let output = BufWriter::new(File::create(&filename).unwrap());
+        let mut writer = PbfWriter::with_optional_features(output, None, &[SORTED_FEATURE]).unwrap();
+        for id in 1..=8001 {
+            writer.write_node(&create_node(id)).unwrap();
+        }
+        writer.write_way(&create_way(1)).unwrap();
+        writer.write_way(&create_way(2)).unwrap();
+        let mut output = writer.finish().unwrap();
+        let transition = create_way_and_relation_block();
+        write_blob(&mut output, "OSMData", &transition.write_to_bytes().unwrap()).unwrap();
+        output.flush().unwrap();
+        drop(output);
+
+        let index = BlockIndex::new(&filename).unwrap();
+        let (nodes, ways, relations) = read_ids(&index);
+        std::fs::remove_file(&filename).unwrap();
+
+        assert_eq!(
+            (index.nodes.clone(), index.ways.clone(), index.relations.clone()),
+            (0..2, 2..4, 3..4)
+        );
+        assert_eq!(nodes, (1..=8001).collect::<Vec<i64>>());
+        assert_eq!(ways, vec![1, 2, 3]);
+        assert_eq!(relations, vec![1]);
+    }
+
+    #[test]
+    fn test_unsorted_file_reads_all_blocks_for_every_type() {
+        let filename = temporary_filename("unsorted");
+        let mut writer = PbfWriter::new(BufWriter::new(File::create(&filename).unwrap()), None).unwrap();
+        writer.write_relation(&create_relation(7)).unwrap();
+        writer.write_way(&create_way(5)).unwrap();
+        writer.write_node(&create_node(2)).unwrap();
+        writer.write_node(&create_node(1)).unwrap();
+        writer.finish().unwrap();
+
+        let index = BlockIndex::new(&filename).unwrap();
+        let (nodes, ways, relations) = read_ids(&index);
+        let node = index.nodes(|node| (node.id == NodeId(2)).then_some(node)).unwrap();
+        std::fs::remove_file(&filename).unwrap();
+
+        assert_eq!(
+            (index.nodes.clone(), index.ways.clone(), index.relations.clone()),
+            (0..3, 0..3, 0..3)
+        );
+        assert_eq!((nodes, ways, relations), (vec![1, 2], vec![5], vec![7]));
+        assert_eq!(node, vec![create_node(2)]);
+    }
+
+    #[test]
+    fn test_primitive_types_of_mixed_and_empty_blocks() {
+        let mut nodes = PrimitiveGroup::new();
+        nodes.mut_dense().mut_id().push(1);
+        let mut ways = PrimitiveGroup::new();
+        ways.mut_ways().push(PbfWay::new());
+
+        let mut mixed = PrimitiveBlock::new();
+        mixed.mut_primitivegroup().push(nodes);
+        mixed.mut_primitivegroup().push(ways);
+
+        assert_eq!(primitive_types(&mixed), (Primitive::Node, Primitive::Way));
+        assert_eq!(
+            primitive_types(&PrimitiveBlock::new()),
+            (Primitive::Node, Primitive::Relation)
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use crate::block_index::{BlockIndex, SORTED_FEATURE};
+use crate::extract::pbf_writer::PbfWriter;
+use crate::osm_reader::OsmObjects;
+
+use osmpbfreader::{Tags, Way};
+
+use std::fs::{rename, File};
+use std::io::{BufWriter, Error, ErrorKind, Result};
+
+/*
+    the cache is an .osm.pbf file holding only the objects the selected boundaries are assembled from;
+    the ways are stored without their tags, since only their nodes are needed;
+    the fingerprint of the selection the cache was created with is declared as an optional feature of the header
+*/
+
+const SELECTION_FEATURE_PREFIX: &str = "osm_extract_polygon.selection=";
+
+pub fn write(filename: &str, objects: &OsmObjects, selection: &str) -> Result<()> {
+    // written next to the cache first, so that an interrupted run does not leave a broken cache behind
+    let temporary = format!("{}.tmp", filename);
+    let output = BufWriter::new(File::create(&temporary)?);
+    let selection_feature = format!("{}{}", SELECTION_FEATURE_PREFIX, selection);
+    let mut writer = PbfWriter::with_optional_features(output, None, &[SORTED_FEATURE, &selection_feature])?;
+
+    let mut node_ids: Vec<_> = objects.nodes.keys().collect();
+    node_ids.sort_unstable();
+    for node_id in node_ids {
+        writer.write_node(&objects.nodes[node_id])?;
+    }
+
+    let mut way_ids: Vec<_> = objects.ways.keys().collect();
+    way_ids.sort_unstable();
+    for way_id in way_ids {
+        writer.write_way(&Way {
+            id: *way_id,
+            tags: Tags::new(),
+            nodes: objects.ways[way_id].clone(),
+        })?;
+    }
+
+    let mut relation_ids: Vec<_> = objects.relations.keys().collect();
+    relation_ids.sort_unstable();
+    for relation_id in relation_ids {
+        writer.write_relation(&objects.relations[relation_id])?;
+    }
+
+    writer.finish()?;
+    rename(&temporary, filename)
+}
+
+pub fn read(filename: &str, selection: &str) -> Result<OsmObjects> {
+    let index = BlockIndex::new(filename)?;
+
+    let selection_feature = format!("{}{}", SELECTION_FEATURE_PREFIX, selection);
+    if !index.optional_features().contains(&selection_feature) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "{}: the cache was created with other selection options, use the same options or create the cache again",
+                filename
+            ),
+        ));
+    }
+
+    Ok(OsmObjects {
+        relations: index
+            .relations(|relation| Some((relation.id, relation)))?
+            .into_iter()
+            .collect(),
+        ways: index.ways(|way| Some((way.id, way.nodes)))?.into_iter().collect(),
+        nodes: index.nodes(|node| Some((node.id, node)))?.into_iter().collect(),
+    })
+}
@@ -0,0 +1,206 @@
+use crate::osm_reader::RelationNodes;
+
+use osmpbfreader::Node;
+use serde_json::{json, Value};
+
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Tags,
+    Members,
+    Geometry,
+}
+
+impl ChangeKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Tags => "tags",
+            ChangeKind::Members => "members",
+            ChangeKind::Geometry => "geometry",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RelationChange {
+    pub relation_id: i64,
+    pub name: String,
+    pub kinds: Vec<ChangeKind>,
+}
+
+impl RelationChange {
+    pub fn is_removed(&self) -> bool {
+        self.kinds.contains(&ChangeKind::Removed)
+    }
+}
+
+pub fn detect(old: &[RelationNodes], new: &[RelationNodes]) -> Vec<RelationChange> {
+    /*
+        a relation changed if its tags or its list of members changed, or if any of the nodes of its member ways
+        (or its admin_centre and label nodes) moved, which also covers ways that got other nodes;
+        changed tags of the admin_centre and label nodes count as a change of the geometry as well
+    */
+    let old_by_id: HashMap<i64, &RelationNodes> = old.iter().map(|r| (r.relation.id.0, r)).collect();
+    let new_by_id: HashMap<i64, &RelationNodes> = new.iter().map(|r| (r.relation.id.0, r)).collect();
+
+    let mut changes: Vec<RelationChange> = new
+        .iter()
+        .filter_map(|current| {
+            let kinds = match old_by_id.get(&current.relation.id.0) {
+                None => vec![ChangeKind::Added],
+                Some(previous) => compare(previous, current),
+            };
+            (!kinds.is_empty()).then(|| change(current, kinds))
+        })
+        .chain(
+            old.iter()
+                .filter(|previous| !new_by_id.contains_key(&previous.relation.id.0))
+                .map(|previous| change(previous, vec![ChangeKind::Removed])),
+        )
+        .collect();
+
+    changes.sort_by_key(|c| c.relation_id);
+    changes
+}
+
+fn compare(previous: &RelationNodes, current: &RelationNodes) -> Vec<ChangeKind> {
+    let mut kinds = Vec::new();
+
+    if previous.relation.tags != current.relation.tags {
+        kinds.push(ChangeKind::Tags);
+    }
+    if previous.relation.refs != current.relation.refs {
+        kinds.push(ChangeKind::Members);
+    }
+    if way_coordinates(previous) != way_coordinates(current) || member_nodes(previous) != member_nodes(current) {
+        kinds.push(ChangeKind::Geometry);
+    }
+
+    kinds
+}
+
+fn way_coordinates(relation: &RelationNodes) -> Vec<Vec<(i32, i32)>> {
+    relation
+        .nodes
+        .iter()
+        .map(|way| way.iter().map(|n| (n.decimicro_lat, n.decimicro_lon)).collect())
+        .collect()
+}
+
+// the tags are compared as well, since the name, place, and population of the nodes are part of the output
+fn member_nodes(relation: &RelationNodes) -> Vec<(&str, &Node)> {
+    relation
+        .member_nodes
+        .iter()
+        .map(|(role, n)| (role.as_str(), n))
+        .collect()
+}
+
+fn change(relation: &RelationNodes, kinds: Vec<ChangeKind>) -> RelationChange {
+    RelationChange {
+        relation_id: relation.relation.id.0,
+        name: relation
+            .relation
+            .tags
+            .get("name")
+            .map(|name| name.to_string())
+            .unwrap_or_default(),
+        kinds,
+    }
+}
+
+pub fn render(changes: &[RelationChange]) -> Value {
+    let relations: Vec<Value> = changes
+        .iter()
+        .map(|c| {
+            json!({
+                "relation_id": c.relation_id,
+                "name": c.name,
+                "changes": c.kinds.iter().map(|k| k.as_str()).collect::<Vec<&str>>(),
+            })
+        })
+        .collect();
+
+    json!({ "relations": relations })
+}
+
+pub fn describe(change: &RelationChange) -> String {
+    let kinds: Vec<&str> = change.kinds.iter().map(|k| k.as_str()).collect();
+    format!(
+        "relation {} ({}): {}",
+        change.relation_id,
+        change.name,
+        kinds.join(", ")
+    )
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use osmpbfreader::{NodeId, OsmId, Ref, Relation, RelationId, Tags, WayId};
+
+    fn relation(id: i64, name: &str, node_lat: i32) -> RelationNodes {
+        let mut tags = Tags::new();
+        tags.insert("name".into(), name.into());
+
+        RelationNodes {
+            relation: Relation {
+                id: RelationId(id),
+                tags,
+                refs: vec![Ref {
+                    member: OsmId::Way(WayId(1)),
+                    role: "outer".into(),
+                }],
+            },
+            nodes: vec![vec![Node {
+                id: NodeId(1),
+                tags: Tags::new(),
+                decimicro_lat: node_lat,
+                decimicro_lon: 0,
+            }]],
+            member_nodes: vec![],
+        }
+    }
+
+    #[test]
+    fn test_detect_lists_added_removed_and_changed_relations() {
+        let mut without_members = relation(5, "Old Name", 0);
+        without_members.relation.refs.clear();
+        let old = vec![
+            relation(1, "Same", 0),
+            relation(2, "Moved", 0),
+            relation(3, "Gone", 0),
+            without_members,
+        ];
+        let new = vec![
+            relation(1, "Same", 0),
+            relation(2, "Moved", 10),
+            relation(4, "New", 0),
+            relation(5, "New Name", 0),
+        ];
+
+        let changes = detect(&old, &new);
+
+        let kinds: Vec<(i64, Vec<ChangeKind>)> = changes.into_iter().map(|c| (c.relation_id, c.kinds)).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (2, vec![ChangeKind::Geometry]),
+                (3, vec![ChangeKind::Removed]),
+                (4, vec![ChangeKind::Added]),
+                (5, vec![ChangeKind::Tags, ChangeKind::Members]),
+            ]
+        );
+    }
+}
@@ -1,21 +1,52 @@
 use crate::osm_reader::RelationNodes;
 use osmpbfreader::Tags;
 use osmpbfreader::{Node, NodeId};
+use std::collections::BTreeMap;
 use std::fmt;
 
+#[derive(Default)]
 pub struct Polygon {
     pub name: String,
     pub points: Vec<Vec<Point>>,
     pub relation_id: i64,
     pub admin_level: i64,
+    pub subarea_ids: Vec<i64>,
+    pub ancestors: Vec<Ancestor>,
+    pub tags: BTreeMap<String, String>,
+    pub admin_centre: Option<PlaceNode>,
+    pub label: Option<PlaceNode>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default, PartialEq)]
 pub struct Point {
     pub lat: f32,
     pub lon: f32,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ancestor {
+    pub relation_id: i64,
+    pub name: String,
+    pub admin_level: i64,
+}
+
+// a node member of a boundary relation, e.g., its admin_centre
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlaceNode {
+    pub node_id: i64,
+    pub lat: f64,
+    pub lon: f64,
+    pub name: Option<String>,
+    pub place: Option<String>,
+    pub population: Option<String>,
+}
+
+impl Polygon {
+    pub fn parent_relation_id(&self) -> Option<i64> {
+        self.ancestors.last().map(|a| a.relation_id)
+    }
+}
+
 impl fmt::Debug for Polygon {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "RelationNodes {{ name: {}, points: {:?} }}", self.name, self.points)
@@ -28,11 +59,31 @@ impl fmt::Debug for Point {
     }
 }
 
-pub fn convert(relations: Vec<RelationNodes>) -> Vec<Polygon> {
-    relations.iter().map(merge_nodes).map(convert_to_poly).collect()
+pub struct NameConfiguration {
+    pub name_tags: Vec<String>,
+    pub use_prefix: bool,
+    pub fallback_template: String,
 }
 
-fn merge_nodes(rn: &RelationNodes) -> RelationNodes {
+impl Default for NameConfiguration {
+    fn default() -> NameConfiguration {
+        NameConfiguration {
+            name_tags: vec![String::from("name")],
+            use_prefix: true,
+            fallback_template: String::from("UNKNOWN_NAME"),
+        }
+    }
+}
+
+pub fn convert(relations: Vec<RelationNodes>, name_config: &NameConfiguration) -> Vec<Polygon> {
+    relations
+        .iter()
+        .map(merge_nodes)
+        .map(|rn| convert_to_poly(rn, name_config))
+        .collect()
+}
+
+pub fn merge_nodes(rn: &RelationNodes) -> RelationNodes {
     /*
         merging of nodes is necessary because ways are split into multiple groups
         assumption:
@@ -77,6 +128,7 @@ fn merge_nodes(rn: &RelationNodes) -> RelationNodes {
     RelationNodes {
         relation: rn.relation.clone(),
         nodes: result_nodes,
+        member_nodes: rn.member_nodes.clone(),
     }
 }
 
@@ -104,14 +156,29 @@ fn find_match(node_id: NodeId, nodes: &mut Vec<Vec<Node>>) -> Option<Vec<Node>>
     None
 }
 
-fn get_full_name(tags: &Tags) -> String {
-    let name = tags
-        .get("name")
-        .map(|x| x.to_string())
-        .unwrap_or(String::from("UNKNOWN_NAME"));
+fn get_full_name(tags: &Tags, relation_id: i64, admin_level: i64, config: &NameConfiguration) -> String {
+    let name = config
+        .name_tags
+        .iter()
+        .filter_map(|tag| tags.get(tag.as_str()))
+        .find(|name| !name.trim().is_empty())
+        .map(|x| x.to_string());
+
+    let name = match name {
+        Some(name) => name,
+        None => {
+            // the prefix is not added to the fallback, since it would be the only distinguishing part
+            return config
+                .fallback_template
+                .replace("{relation_id}", &relation_id.to_string())
+                .replace("{id}", &relation_id.to_string())
+                .replace("{admin_level}", &admin_level.to_string());
+        }
+    };
 
     let name_prefix = tags
         .get("name:prefix")
+        .filter(|_| config.use_prefix)
         .map(|x| x.to_string())
         .unwrap_or(String::from(""));
 
@@ -122,22 +189,46 @@ fn get_full_name(tags: &Tags) -> String {
     }
 }
 
-fn convert_to_poly(rn: RelationNodes) -> Polygon {
+fn convert_to_poly(rn: RelationNodes, name_config: &NameConfiguration) -> Polygon {
     let points = rn.nodes.iter().map(|x| convert_nodes_to_points(x)).collect();
     let relation_id: i64 = rn.relation.id.0;
     let tags = &rn.relation.tags;
 
-    let fullname = get_full_name(tags);
     let admin_level = tags.get("admin_level").and_then(|x| x.parse::<i64>().ok()).unwrap_or(0);
+    let fullname = get_full_name(tags, relation_id, admin_level, name_config);
+    let subarea_ids = rn
+        .relation
+        .refs
+        .iter()
+        .filter(|r| r.role == "subarea")
+        .filter_map(|r| r.member.relation())
+        .map(|id| id.0)
+        .collect();
 
     Polygon {
         name: fullname,
         points,
         relation_id,
         admin_level,
+        subarea_ids,
+        ancestors: Vec::new(),
+        tags: tags.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        admin_centre: find_place_node(&rn.member_nodes, "admin_centre"),
+        label: find_place_node(&rn.member_nodes, "label"),
     }
 }
 
+fn find_place_node(member_nodes: &[(String, Node)], role: &str) -> Option<PlaceNode> {
+    member_nodes.iter().find(|(r, _)| r == role).map(|(_, node)| PlaceNode {
+        node_id: node.id.0,
+        lat: (node.decimicro_lat as f64) / 10_000_000.0,
+        lon: (node.decimicro_lon as f64) / 10_000_000.0,
+        name: node.tags.get("name").map(|v| v.to_string()),
+        place: node.tags.get("place").map(|v| v.to_string()),
+        population: node.tags.get("population").map(|v| v.to_string()),
+    })
+}
+
 fn convert_nodes_to_points(nodes: &[Node]) -> Vec<Point> {
     nodes
         .iter()
@@ -147,3 +238,108 @@ fn convert_nodes_to_points(nodes: &[Node]) -> Vec<Point> {
         })
         .collect()
 }
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::iter::FromIterator;
+
+    use smartstring::alias::String as SmartString;
+
+    fn create_tags(pairs: &[(&str, &str)]) -> Tags {
+        Tags::from_iter(
+            pairs
+                .iter()
+                .map(|(k, v)| (SmartString::from(*k), SmartString::from(*v))),
+        )
+    }
+
+    fn create_config(name_tags: &[&str], use_prefix: bool, fallback_template: &str) -> NameConfiguration {
+        NameConfiguration {
+            name_tags: name_tags.iter().map(|t| t.to_string()).collect(),
+            use_prefix,
+            fallback_template: fallback_template.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_find_place_node_uses_first_node_with_role() {
+        let node = |id: i64, tags: &[(&str, &str)]| Node {
+            id: NodeId(id),
+            tags: create_tags(tags),
+            decimicro_lat: 490_094_000,
+            decimicro_lon: 84_044_000,
+        };
+        let member_nodes = vec![
+            (String::from("label"), node(1, &[])),
+            (
+                String::from("admin_centre"),
+                node(2, &[("name", "Karlsruhe"), ("place", "city"), ("population", "308436")]),
+            ),
+            (String::from("admin_centre"), node(3, &[])),
+        ];
+
+        let result = find_place_node(&member_nodes, "admin_centre").unwrap();
+
+        assert_eq!(result.node_id, 2);
+        assert!((result.lat - 49.0094).abs() < 1e-9 && (result.lon - 8.4044).abs() < 1e-9);
+        assert_eq!(result.name.as_deref(), Some("Karlsruhe"));
+        assert_eq!(result.place.as_deref(), Some("city"));
+        assert_eq!(result.population.as_deref(), Some("308436"));
+        assert!(find_place_node(&member_nodes[..1], "admin_centre").is_none());
+    }
+
+    #[test]
+    fn test_get_full_name_defaults_to_name_with_prefix() {
+        let tags = create_tags(&[("name", "Karlsruhe"), ("name:prefix", "Stadt")]);
+
+        let result = get_full_name(&tags, 1, 8, &NameConfiguration::default());
+
+        assert_eq!(result, "Stadt_Karlsruhe");
+    }
+
+    #[test]
+    fn test_get_full_name_defaults_to_unknown_name() {
+        let tags = create_tags(&[]);
+
+        let result = get_full_name(&tags, 1, 8, &NameConfiguration::default());
+
+        assert_eq!(result, "UNKNOWN_NAME");
+    }
+
+    #[test]
+    fn test_get_full_name_uses_first_available_name_tag() {
+        let tags = create_tags(&[("name", "München"), ("int_name", "Munich"), ("name:fr", "")]);
+        let config = create_config(&["name:fr", "name:en", "int_name", "name"], true, "UNKNOWN_NAME");
+
+        let result = get_full_name(&tags, 1, 8, &config);
+
+        assert_eq!(result, "Munich");
+    }
+
+    #[test]
+    fn test_get_full_name_can_omit_prefix() {
+        let tags = create_tags(&[("name", "Karlsruhe"), ("name:prefix", "Stadt")]);
+        let config = create_config(&["name"], false, "UNKNOWN_NAME");
+
+        let result = get_full_name(&tags, 1, 8, &config);
+
+        assert_eq!(result, "Karlsruhe");
+    }
+
+    #[test]
+    fn test_get_full_name_expands_fallback_template() {
+        let tags = create_tags(&[("name:prefix", "Stadt")]);
+        let config = create_config(&["name"], true, "relation_{id}_level_{admin_level}");
+
+        let result = get_full_name(&tags, 62422, 8, &config);
+
+        assert_eq!(result, "relation_62422_level_8");
+    }
+}
@@ -0,0 +1,458 @@
+use crate::converter::Polygon;
+use crate::geometry::to_multi_polygon;
+use crate::output::file_creator::FileCreator;
+use crate::output::manifest::content_hash;
+use crate::poly_reader::read_poly;
+
+use geo::{BooleanOps, BoundingRect, CoordsIter, GeodesicArea};
+use geo_types::{Coord, MultiPolygon};
+use geojson::{Feature, FeatureCollection, Geometry};
+use rstar::primitives::Line;
+use rstar::{PointDistance, RTree};
+use serde_json::{json, Map, Value};
+
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+pub struct Thresholds {
+    pub hausdorff_m: f64,
+    pub area_km2: f64,
+}
+
+struct PreviousBoundary {
+    relation_id: i64,
+    name: String,
+    admin_level: i64,
+    content_hash: String,
+    poly_file: Option<PathBuf>,
+}
+
+struct GeometryChange {
+    relation_id: i64,
+    name: String,
+    hausdorff_m: f64,
+    symmetric_difference_km2: f64,
+    difference: MultiPolygon<f64>,
+}
+
+#[derive(Default)]
+struct Report {
+    added: Vec<Value>,
+    removed: Vec<Value>,
+    renamed: Vec<Value>,
+    changed: Vec<GeometryChange>,
+    unchanged: usize,
+    unreadable: Vec<Value>,
+    changed_areas: Vec<Feature>,
+}
+
+pub fn run(
+    polygons: &[Polygon],
+    previous_folder: &str,
+    thresholds: &Thresholds,
+    report_filename: &Path,
+    changes_filename: Option<&Path>,
+    file_creator: &mut FileCreator,
+) -> Result<(), String> {
+    let previous = read_previous(Path::new(previous_folder))?;
+    let report = compare(polygons, &previous, thresholds);
+
+    println!(
+        "{} added, {} removed, {} renamed, {} changed, {} unchanged, {} unreadable",
+        report.added.len(),
+        report.removed.len(),
+        report.renamed.len(),
+        report.changed.len(),
+        report.unchanged,
+        report.unreadable.len()
+    );
+
+    let write = |file_creator: &mut FileCreator, filename: &Path, content: String| {
+        file_creator
+            .create_file(filename)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .map_err(|e| format!("{}: {}", filename.display(), e))
+    };
+
+    write(
+        file_creator,
+        report_filename,
+        render_report(&report, thresholds).to_string(),
+    )?;
+    println!("{}: successfully written", report_filename.display());
+
+    if let Some(changes_filename) = changes_filename {
+        let changes = FeatureCollection {
+            bbox: None,
+            features: report.changed_areas,
+            foreign_members: None,
+        };
+        write(file_creator, changes_filename, changes.to_string())?;
+        println!("{}: successfully written", changes_filename.display());
+    }
+
+    Ok(())
+}
+
+fn read_previous(folder: &Path) -> Result<Vec<PreviousBoundary>, String> {
+    // the relation ids of the previous files are only known from the manifest
+    let manifest_filename = folder.join("manifest.json");
+    let text = read_to_string(&manifest_filename).map_err(|e| {
+        format!(
+            "{}: {} (the previous extraction has to be run with --manifest)",
+            manifest_filename.display(),
+            e
+        )
+    })?;
+    let manifest: Value = serde_json::from_str(&text).map_err(|e| format!("{}: {}", manifest_filename.display(), e))?;
+
+    let boundaries = manifest["boundaries"]
+        .as_array()
+        .ok_or_else(|| format!("{}: no boundaries found", manifest_filename.display()))?;
+
+    boundaries
+        .iter()
+        .map(|boundary| {
+            let relation_id = boundary["relation_id"]
+                .as_i64()
+                .ok_or_else(|| format!("{}: boundary without relation_id", manifest_filename.display()))?;
+            let poly_file = boundary["files"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|file| file.as_str())
+                .find(|file| file.ends_with(".poly"))
+                .map(|file| folder.join(file));
+
+            Ok(PreviousBoundary {
+                relation_id,
+                name: boundary["name"].as_str().unwrap_or_default().to_string(),
+                admin_level: boundary["admin_level"].as_i64().unwrap_or_default(),
+                content_hash: boundary["content_hash"].as_str().unwrap_or_default().to_string(),
+                poly_file,
+            })
+        })
+        .collect()
+}
+
+fn compare(polygons: &[Polygon], previous: &[PreviousBoundary], thresholds: &Thresholds) -> Report {
+    let mut report = Report::default();
+    let previous_by_id: HashMap<i64, &PreviousBoundary> = previous.iter().map(|p| (p.relation_id, p)).collect();
+    let current_by_id: HashMap<i64, &Polygon> = polygons.iter().map(|p| (p.relation_id, p)).collect();
+
+    let mut polygons: Vec<&Polygon> = polygons.iter().collect();
+    polygons.sort_by_key(|p| p.relation_id);
+
+    for polygon in polygons {
+        let old = match previous_by_id.get(&polygon.relation_id) {
+            Some(old) => old,
+            None => {
+                report
+                    .added
+                    .push(describe(polygon.relation_id, &polygon.name, polygon.admin_level));
+                report.changed_areas.push(change_feature(
+                    polygon.relation_id,
+                    &polygon.name,
+                    "added",
+                    &to_multi_polygon(polygon),
+                ));
+                continue;
+            }
+        };
+
+        if old.name != polygon.name {
+            report.renamed.push(json!({
+                "relation_id": polygon.relation_id,
+                "old_name": old.name,
+                "new_name": polygon.name,
+            }));
+        }
+
+        // the hash covers the name as well, so only equal hashes allow to skip the comparison
+        if old.content_hash == content_hash(polygon) {
+            report.unchanged += 1;
+            continue;
+        }
+
+        let old_geometry = match read_previous_geometry(old) {
+            Ok(old_geometry) => old_geometry.unwrap_or_else(|| MultiPolygon(vec![])),
+            Err(e) => {
+                report.unreadable.push(unreadable(old, e));
+                continue;
+            }
+        };
+        let change = measure_change(polygon, &old_geometry);
+
+        if change.hausdorff_m > thresholds.hausdorff_m || change.symmetric_difference_km2 > thresholds.area_km2 {
+            report.changed_areas.push(change_feature(
+                polygon.relation_id,
+                &polygon.name,
+                "changed",
+                &change.difference,
+            ));
+            report.changed.push(change);
+        } else {
+            report.unchanged += 1;
+        }
+    }
+
+    let mut removed: Vec<&&PreviousBoundary> = previous_by_id
+        .values()
+        .filter(|old| !current_by_id.contains_key(&old.relation_id))
+        .collect();
+    removed.sort_by_key(|old| old.relation_id);
+    for old in removed {
+        report
+            .removed
+            .push(describe(old.relation_id, &old.name, old.admin_level));
+        match read_previous_geometry(old) {
+            Ok(Some(old_geometry)) => {
+                report
+                    .changed_areas
+                    .push(change_feature(old.relation_id, &old.name, "removed", &old_geometry))
+            }
+            Ok(None) => {}
+            Err(e) => report.unreadable.push(unreadable(old, e)),
+        }
+    }
+
+    report
+}
+
+fn read_previous_geometry(old: &PreviousBoundary) -> Result<Option<MultiPolygon<f64>>, String> {
+    match &old.poly_file {
+        Some(filename) => read_poly(&filename.to_string_lossy()).map(|poly| Some(poly.to_multi_polygon())),
+        None => Ok(None),
+    }
+}
+
+// boundaries whose previous .poly file cannot be read are neither changed nor unchanged
+fn unreadable(old: &PreviousBoundary, error: String) -> Value {
+    println!("warning: relation {} is not compared: {}", old.relation_id, error);
+    json!({ "relation_id": old.relation_id, "name": old.name, "error": error })
+}
+
+fn measure_change(polygon: &Polygon, old_geometry: &MultiPolygon<f64>) -> GeometryChange {
+    let new_geometry = to_multi_polygon(polygon);
+    let difference = new_geometry.xor(old_geometry);
+
+    GeometryChange {
+        relation_id: polygon.relation_id,
+        name: polygon.name.clone(),
+        hausdorff_m: hausdorff_distance_m(&new_geometry, old_geometry),
+        symmetric_difference_km2: difference.geodesic_area_unsigned() / 1_000_000.0,
+        difference,
+    }
+}
+
+fn hausdorff_distance_m(a: &MultiPolygon<f64>, b: &MultiPolygon<f64>) -> f64 {
+    /*
+        Hausdorff distance between both geometries, measured from the vertices of each geometry to the
+        nearest edge of the other one, so that vertices added to or removed from a straight line do not count;
+        the coordinates are projected to meters with an equirectangular projection around the center of both
+        geometries, which is accurate enough for the comparison of a boundary with a previous version of itself
+    */
+    let (rect_a, rect_b) = match (a.bounding_rect(), b.bounding_rect()) {
+        (Some(rect_a), Some(rect_b)) => (rect_a, rect_b),
+        (None, None) => return 0.0,
+        _ => return f64::INFINITY,
+    };
+    let center_lat = (rect_a.min().y.min(rect_b.min().y) + rect_a.max().y.max(rect_b.max().y)) / 2.0;
+    let scale = EARTH_RADIUS_M.to_radians();
+    let project = |c: Coord<f64>| [c.x * scale * center_lat.to_radians().cos(), c.y * scale];
+
+    directed_hausdorff(a, b, &project).max(directed_hausdorff(b, a, &project))
+}
+
+fn directed_hausdorff(
+    from: &MultiPolygon<f64>,
+    to: &MultiPolygon<f64>,
+    project: &impl Fn(Coord<f64>) -> [f64; 2],
+) -> f64 {
+    let edges: Vec<Line<[f64; 2]>> =
+        to.0.iter()
+            .flat_map(|part| std::iter::once(part.exterior()).chain(part.interiors()))
+            .flat_map(|ring| ring.lines())
+            .map(|line| Line::new(project(line.start), project(line.end)))
+            .collect();
+    let tree = RTree::bulk_load(edges);
+
+    from.coords_iter()
+        .map(project)
+        .filter_map(|p| tree.nearest_neighbor(&p).map(|edge| edge.distance_2(&p).sqrt()))
+        .fold(0.0, f64::max)
+}
+
+fn describe(relation_id: i64, name: &str, admin_level: i64) -> Value {
+    json!({ "relation_id": relation_id, "name": name, "admin_level": admin_level })
+}
+
+fn change_feature(relation_id: i64, name: &str, change: &str, geometry: &MultiPolygon<f64>) -> Feature {
+    let mut properties = Map::new();
+    properties.insert(String::from("relation_id"), json!(relation_id));
+    properties.insert(String::from("name"), json!(name));
+    properties.insert(String::from("change"), json!(change));
+
+    Feature {
+        bbox: None,
+        geometry: Some(Geometry::new(geojson::Value::from(geometry))),
+        id: None,
+        properties: Some(properties),
+        foreign_members: None,
+    }
+}
+
+fn render_report(report: &Report, thresholds: &Thresholds) -> Value {
+    let changed: Vec<Value> = report
+        .changed
+        .iter()
+        .map(|change| {
+            json!({
+                "relation_id": change.relation_id,
+                "name": change.name,
+                // infinite if one of the versions has no geometry
+                "hausdorff_m": if change.hausdorff_m.is_finite() { json!(change.hausdorff_m) } else { Value::Null },
+                "symmetric_difference_km2": change.symmetric_difference_km2,
+            })
+        })
+        .collect();
+
+    json!({
+        "thresholds": { "hausdorff_m": thresholds.hausdorff_m, "area_km2": thresholds.area_km2 },
+        "summary": {
+            "added": report.added.len(),
+            "removed": report.removed.len(),
+            "renamed": report.renamed.len(),
+            "changed": report.changed.len(),
+            "unchanged": report.unchanged,
+            "unreadable": report.unreadable.len(),
+        },
+        "added": report.added,
+        "removed": report.removed,
+        "renamed": report.renamed,
+        "changed": changed,
+        "unreadable": report.unreadable,
+    })
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::Point;
+    use crate::geometry::square_ring;
+
+    fn square(relation_id: i64, name: &str, size: f32) -> Polygon {
+        Polygon {
+            name: String::from(name),
+            points: vec![square_ring(0.0, size)],
+            relation_id,
+            admin_level: 8,
+            ..Default::default()
+        }
+    }
+
+    fn previous(polygon: &Polygon) -> PreviousBoundary {
+        PreviousBoundary {
+            relation_id: polygon.relation_id,
+            name: polygon.name.clone(),
+            admin_level: polygon.admin_level,
+            content_hash: content_hash(polygon),
+            poly_file: None,
+        }
+    }
+
+    #[test]
+    fn test_compare_finds_added_removed_and_renamed_boundaries() {
+        let thresholds = Thresholds {
+            hausdorff_m: 0.0,
+            area_km2: 0.0,
+        };
+        let mut renamed = previous(&square(3, "Old Name", 1.0));
+        renamed.content_hash = content_hash(&square(3, "New Name", 1.0));
+        let old = vec![
+            previous(&square(1, "Same", 1.0)),
+            previous(&square(2, "Gone", 1.0)),
+            renamed,
+        ];
+
+        let current = vec![
+            square(1, "Same", 1.0),
+            square(3, "New Name", 1.0),
+            square(4, "New", 1.0),
+        ];
+        let report = compare(&current, &old, &thresholds);
+
+        assert_eq!(report.added, vec![describe(4, "New", 8)]);
+        assert_eq!(report.removed, vec![describe(2, "Gone", 8)]);
+        assert_eq!(report.renamed.len(), 1);
+        assert_eq!(report.renamed[0]["new_name"], "New Name");
+        assert_eq!(report.unchanged, 2);
+        assert!(report.changed.is_empty());
+    }
+
+    #[test]
+    fn test_compare_reports_unreadable_previous_files() {
+        let thresholds = Thresholds {
+            hausdorff_m: 0.0,
+            area_km2: 0.0,
+        };
+        let missing = |polygon: &Polygon| PreviousBoundary {
+            content_hash: String::new(),
+            poly_file: Some(PathBuf::from("/nonexistent/previous.poly")),
+            ..previous(polygon)
+        };
+        let old = vec![missing(&square(1, "Changed", 1.0)), missing(&square(2, "Gone", 1.0))];
+
+        let report = compare(&[square(1, "Changed", 1.0)], &old, &thresholds);
+
+        assert!(report.changed.is_empty());
+        assert_eq!(report.removed, vec![describe(2, "Gone", 8)]);
+        assert!(report.changed_areas.is_empty());
+        let ids: Vec<&Value> = report.unreadable.iter().map(|u| &u["relation_id"]).collect();
+        assert_eq!(ids, vec![&json!(1), &json!(2)]);
+    }
+
+    #[test]
+    fn test_measure_change_of_grown_square() {
+        let old_geometry = to_multi_polygon(&square(1, "A", 1.0));
+
+        let change = measure_change(&square(1, "A", 1.01), &old_geometry);
+
+        // 0.01° of latitude are about 1.1 km, the diagonal corner moved by about 1.57 km
+        assert!((change.hausdorff_m - 1_570.0).abs() < 30.0, "{}", change.hausdorff_m);
+        assert!(
+            (change.symmetric_difference_km2 - 246.0).abs() < 5.0,
+            "{}",
+            change.symmetric_difference_km2
+        );
+    }
+
+    #[test]
+    fn test_hausdorff_distance_of_equal_geometries_is_zero() {
+        let geometry = to_multi_polygon(&square(1, "A", 1.0));
+
+        assert_eq!(hausdorff_distance_m(&geometry, &geometry), 0.0);
+        assert_eq!(hausdorff_distance_m(&geometry, &MultiPolygon(vec![])), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_hausdorff_distance_ignores_vertices_added_to_an_edge() {
+        let mut polygon = square(1, "A", 1.0);
+        let geometry = to_multi_polygon(&polygon);
+        polygon.points[0].insert(1, Point { lat: 0.0, lon: 0.5 });
+
+        let distance = hausdorff_distance_m(&to_multi_polygon(&polygon), &geometry);
+
+        assert!(distance < 1.0, "{}", distance);
+    }
+}
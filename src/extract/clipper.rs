@@ -0,0 +1,244 @@
+use crate::block_index::BlockIndex;
+use crate::converter::Polygon;
+use crate::extract::pbf_writer::PbfWriter;
+use crate::extract::ExtractStrategy;
+use crate::geometry::{polygon_contains, BoundingBox};
+use crate::output::file_creator::FileCreator;
+use crate::output::manifest::Manifest;
+
+use osmpbfreader::{Node, NodeId, OsmId, Relation, RelationId, Way, WayId};
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Result};
+use std::path::PathBuf;
+use std::time::Instant;
+
+struct Extract<'a> {
+    polygon: &'a Polygon,
+    bbox: BoundingBox,
+    filename: PathBuf,
+}
+
+impl<'a> Extract<'a> {
+    fn contains(&self, node: &Node) -> bool {
+        self.bbox.contains(node.lon(), node.lat()) && polygon_contains(self.polygon, node.lon(), node.lat())
+    }
+}
+
+type Writer = PbfWriter<BufWriter<File>>;
+
+pub fn extract(
+    filename: &str,
+    targets: Vec<(PathBuf, &Polygon)>,
+    strategy: ExtractStrategy,
+    file_creator: &mut FileCreator,
+    manifest: &mut Manifest,
+) -> Result<u64> {
+    let now = Instant::now();
+    println!("writing extracts...");
+
+    let (extracts, mut writers): (Vec<Extract>, Vec<Writer>) = targets
+        .into_iter()
+        .filter_map(|(target, polygon)| create_extract(target, polygon, file_creator, manifest))
+        .unzip();
+
+    if extracts.is_empty() {
+        return Ok(0);
+    }
+
+    /*
+        every type is read in a phase of its own, so the order of the objects in the file does not matter;
+        the selected ids are kept apart from the writers, since they are read while the blocks are decoded in parallel
+    */
+    let index = BlockIndex::new(filename)?;
+    let mut nodes: Vec<HashSet<NodeId>> = vec![HashSet::new(); extracts.len()];
+    let mut ways: Vec<HashSet<WayId>> = vec![HashSet::new(); extracts.len()];
+    let mut relations: Vec<HashSet<RelationId>> = vec![HashSet::new(); extracts.len()];
+
+    match strategy {
+        ExtractStrategy::Simple => {
+            index.for_each_node(
+                |node| with_selection(node, extracts.len(), |node, i| extracts[i].contains(node)),
+                |(node, selected)| {
+                    for i in selected {
+                        if nodes[i].insert(node.id) {
+                            writers[i].write_node(&node)?;
+                        }
+                    }
+                    Ok(())
+                },
+            )?;
+            index.for_each_way(
+                |way| with_selection(way, nodes.len(), |way, i| references_any(&nodes[i], way)),
+                |(way, selected)| {
+                    for i in selected {
+                        if ways[i].insert(way.id) {
+                            writers[i].write_way(&way)?;
+                        }
+                    }
+                    Ok(())
+                },
+            )?;
+        }
+        ExtractStrategy::CompleteWays => {
+            select_complete_ways(&index, &extracts, &mut nodes, &mut ways)?;
+            index.for_each_node(
+                |node| with_selection(node, nodes.len(), |node, i| nodes[i].contains(&node.id)),
+                |(node, selected)| {
+                    for i in selected {
+                        writers[i].write_node(&node)?;
+                    }
+                    Ok(())
+                },
+            )?;
+            index.for_each_way(
+                |way| with_selection(way, ways.len(), |way, i| ways[i].contains(&way.id)),
+                |(way, selected)| {
+                    for i in selected {
+                        writers[i].write_way(&way)?;
+                    }
+                    Ok(())
+                },
+            )?;
+        }
+    }
+
+    // relations with relation members are checked in the order of the file against the relations selected so far
+    index.for_each_relation(
+        |relation| {
+            let has_relation_members = relation.refs.iter().any(|r| matches!(r.member, OsmId::Relation(_)));
+            let direct: Vec<usize> = (0..nodes.len())
+                .filter(|&i| references_node_or_way(&nodes[i], &ways[i], &relation))
+                .collect();
+            (has_relation_members || !direct.is_empty()).then_some((relation, direct))
+        },
+        |(relation, direct)| {
+            for (i, writer) in writers.iter_mut().enumerate() {
+                let included = direct.contains(&i)
+                    || relation.refs.iter().any(|r| match r.member {
+                        OsmId::Relation(id) => relations[i].contains(&id),
+                        _ => false,
+                    });
+                if included {
+                    relations[i].insert(relation.id);
+                    writer.write_relation(&relation)?;
+                }
+            }
+            Ok(())
+        },
+    )?;
+
+    let mut file_count = 0;
+    for (extract, writer) in extracts.into_iter().zip(writers) {
+        let filename_str = extract.filename.as_os_str().to_str().unwrap().to_string();
+        let result = writer.finish();
+        manifest.record(extract.polygon.relation_id, &extract.filename, &result);
+        match result {
+            Ok(_) => {
+                println!("{}: successfully written ", filename_str);
+                file_count += 1;
+            }
+            Err(e) => println!("{}: {}", filename_str, e),
+        }
+    }
+
+    println!("finished writing extracts! {}s", now.elapsed().as_secs());
+    Ok(file_count)
+}
+
+fn create_extract<'a>(
+    filename: PathBuf,
+    polygon: &'a Polygon,
+    file_creator: &mut FileCreator,
+    manifest: &mut Manifest,
+) -> Option<(Extract<'a>, Writer)> {
+    let bbox = BoundingBox::of_polygon(polygon)?;
+    let writer = file_creator
+        .create_file(&filename)
+        .and_then(|file| PbfWriter::new(BufWriter::new(file), Some(bbox)));
+
+    if writer.is_err() {
+        manifest.record(polygon.relation_id, &filename, &writer);
+    }
+
+    match writer {
+        Err(e) => {
+            println!("{}: {}", filename.as_os_str().to_str().unwrap(), e);
+            None
+        }
+        Ok(writer) => Some((
+            Extract {
+                polygon,
+                bbox,
+                filename,
+            },
+            writer,
+        )),
+    }
+}
+
+fn select_complete_ways(
+    index: &BlockIndex,
+    extracts: &[Extract],
+    nodes: &mut [HashSet<NodeId>],
+    ways: &mut [HashSet<WayId>],
+) -> Result<()> {
+    /*
+        selection for the complete ways strategy:
+         - nodes inside of the boundary are selected
+         - ways referencing a selected node are selected, and all of their nodes are
+           added to the selection so that ways crossing the boundary stay complete
+    */
+    index.for_each_node(
+        |node| {
+            with_selection(node, extracts.len(), |node, i| extracts[i].contains(node))
+                .map(|(node, selected)| (node.id, selected))
+        },
+        |(id, selected)| {
+            for i in selected {
+                nodes[i].insert(id);
+            }
+            Ok(())
+        },
+    )?;
+
+    let mut way_nodes: Vec<HashSet<NodeId>> = vec![HashSet::new(); extracts.len()];
+    index.for_each_way(
+        |way| with_selection(way, extracts.len(), |way, i| references_any(&nodes[i], way)),
+        |(way, selected)| {
+            for i in selected {
+                ways[i].insert(way.id);
+                way_nodes[i].extend(way.nodes.iter().cloned());
+            }
+            Ok(())
+        },
+    )?;
+
+    for (selected, way_nodes) in nodes.iter_mut().zip(way_nodes) {
+        selected.extend(way_nodes);
+    }
+    Ok(())
+}
+
+// the object together with the indices of the extracts it is selected for, or None if there is none
+fn with_selection<T>(object: T, count: usize, is_selected: impl Fn(&T, usize) -> bool) -> Option<(T, Vec<usize>)> {
+    let selected: Vec<usize> = (0..count).filter(|&i| is_selected(&object, i)).collect();
+    if selected.is_empty() {
+        None
+    } else {
+        Some((object, selected))
+    }
+}
+
+fn references_any(nodes: &HashSet<NodeId>, way: &Way) -> bool {
+    way.nodes.iter().any(|n| nodes.contains(n))
+}
+
+fn references_node_or_way(nodes: &HashSet<NodeId>, ways: &HashSet<WayId>, relation: &Relation) -> bool {
+    relation.refs.iter().any(|r| match r.member {
+        OsmId::Node(id) => nodes.contains(&id),
+        OsmId::Way(id) => ways.contains(&id),
+        OsmId::Relation(_) => false,
+    })
+}
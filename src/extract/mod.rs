@@ -0,0 +1,11 @@
+pub mod clipper;
+
+pub mod pbf_writer;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ExtractStrategy {
+    // nodes inside of the boundary, and all ways and relations referencing them
+    Simple,
+    // like simple, but ways crossing the boundary are completed with the nodes outside of it
+    CompleteWays,
+}
@@ -0,0 +1,335 @@
+use crate::geometry::BoundingBox;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use osmpbfreader::fileformat::{Blob, BlobHeader};
+use osmpbfreader::osmformat::{
+    DenseNodes, HeaderBBox, HeaderBlock, PrimitiveBlock, PrimitiveGroup, Relation_MemberType, StringTable,
+};
+use osmpbfreader::{Node, OsmId, Relation, Tags, Way};
+use protobuf::Message;
+
+use std::collections::HashMap;
+use std::io::prelude::*;
+use std::io::{Error, Result};
+
+const MAX_ENTITIES_PER_BLOCK: usize = 8000;
+
+pub struct PbfWriter<W: Write> {
+    output: W,
+    block: BlockBuilder,
+}
+
+impl<W: Write> PbfWriter<W> {
+    pub fn new(output: W, bbox: Option<BoundingBox>) -> Result<PbfWriter<W>> {
+        PbfWriter::with_optional_features(output, bbox, &[])
+    }
+
+    // the optional features (e.g., the sort order) are only declared, the caller has to write the objects accordingly
+    pub fn with_optional_features(
+        mut output: W,
+        bbox: Option<BoundingBox>,
+        optional_features: &[&str],
+    ) -> Result<PbfWriter<W>> {
+        let mut header = HeaderBlock::new();
+        header.mut_required_features().push(String::from("OsmSchema-V0.6"));
+        header.mut_required_features().push(String::from("DenseNodes"));
+        for feature in optional_features {
+            header.mut_optional_features().push(feature.to_string());
+        }
+        header.set_writingprogram(format!("osm_extract_polygon {}", env!("CARGO_PKG_VERSION")));
+        if let Some(b) = bbox {
+            let mut header_bbox = HeaderBBox::new();
+            header_bbox.set_left(to_nanodegrees(b.min_lon));
+            header_bbox.set_right(to_nanodegrees(b.max_lon));
+            header_bbox.set_bottom(to_nanodegrees(b.min_lat));
+            header_bbox.set_top(to_nanodegrees(b.max_lat));
+            header.set_bbox(header_bbox);
+        }
+
+        write_blob(&mut output, "OSMHeader", &to_bytes(&header)?)?;
+
+        Ok(PbfWriter {
+            output,
+            block: BlockBuilder::default(),
+        })
+    }
+
+    pub fn write_node(&mut self, node: &Node) -> Result<()> {
+        self.switch_to(EntityType::Node)?;
+        self.block.add_node(node);
+        self.flush_if_full()
+    }
+
+    pub fn write_way(&mut self, way: &Way) -> Result<()> {
+        self.switch_to(EntityType::Way)?;
+        self.block.add_way(way);
+        self.flush_if_full()
+    }
+
+    pub fn write_relation(&mut self, relation: &Relation) -> Result<()> {
+        self.switch_to(EntityType::Relation)?;
+        self.block.add_relation(relation);
+        self.flush_if_full()
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        self.flush()?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+
+    fn switch_to(&mut self, entity_type: EntityType) -> Result<()> {
+        if self.block.entity_type.is_some() && self.block.entity_type != Some(entity_type) {
+            self.flush()?;
+        }
+        self.block.entity_type = Some(entity_type);
+        Ok(())
+    }
+
+    fn flush_if_full(&mut self) -> Result<()> {
+        if self.block.count >= MAX_ENTITIES_PER_BLOCK {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.block.count == 0 {
+            return Ok(());
+        }
+        let block = std::mem::take(&mut self.block).build();
+        write_blob(&mut self.output, "OSMData", &to_bytes(&block)?)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EntityType {
+    Node,
+    Way,
+    Relation,
+}
+
+#[derive(Default)]
+struct BlockBuilder {
+    entity_type: Option<EntityType>,
+    count: usize,
+    strings: Vec<String>,
+    string_index: HashMap<String, u32>,
+    group: PrimitiveGroup,
+    last_node: (i64, i64, i64),
+}
+
+impl BlockBuilder {
+    fn string_id(&mut self, s: &str) -> u32 {
+        if self.strings.is_empty() {
+            // index 0 is reserved as delimiter in dense nodes
+            self.strings.push(String::new());
+        }
+        if let Some(id) = self.string_index.get(s) {
+            return *id;
+        }
+        let id = self.strings.len() as u32;
+        self.strings.push(s.to_string());
+        self.string_index.insert(s.to_string(), id);
+        id
+    }
+
+    fn tags_to_ids(&mut self, tags: &Tags) -> (Vec<u32>, Vec<u32>) {
+        tags.iter().map(|(k, v)| (self.string_id(k), self.string_id(v))).unzip()
+    }
+
+    fn add_node(&mut self, node: &Node) {
+        let (keys, vals) = self.tags_to_ids(&node.tags);
+        let (last_id, last_lat, last_lon) = self.last_node;
+
+        let dense: &mut DenseNodes = self.group.mut_dense();
+        dense.mut_id().push(node.id.0 - last_id);
+        dense.mut_lat().push(node.decimicro_lat as i64 - last_lat);
+        dense.mut_lon().push(node.decimicro_lon as i64 - last_lon);
+        for (k, v) in keys.into_iter().zip(vals) {
+            dense.mut_keys_vals().push(k as i32);
+            dense.mut_keys_vals().push(v as i32);
+        }
+        dense.mut_keys_vals().push(0);
+
+        self.last_node = (node.id.0, node.decimicro_lat as i64, node.decimicro_lon as i64);
+        self.count += 1;
+    }
+
+    fn add_way(&mut self, way: &Way) {
+        let (keys, vals) = self.tags_to_ids(&way.tags);
+
+        let mut pbf_way = osmpbfreader::osmformat::Way::new();
+        pbf_way.set_id(way.id.0);
+        pbf_way.set_keys(keys);
+        pbf_way.set_vals(vals);
+        let mut last = 0;
+        for node_id in &way.nodes {
+            pbf_way.mut_refs().push(node_id.0 - last);
+            last = node_id.0;
+        }
+
+        self.group.mut_ways().push(pbf_way);
+        self.count += 1;
+    }
+
+    fn add_relation(&mut self, relation: &Relation) {
+        let (keys, vals) = self.tags_to_ids(&relation.tags);
+        let roles: Vec<i32> = relation.refs.iter().map(|r| self.string_id(&r.role) as i32).collect();
+
+        let mut pbf_relation = osmpbfreader::osmformat::Relation::new();
+        pbf_relation.set_id(relation.id.0);
+        pbf_relation.set_keys(keys);
+        pbf_relation.set_vals(vals);
+        pbf_relation.set_roles_sid(roles);
+        let mut last = 0;
+        for r in &relation.refs {
+            let (member_type, id) = match r.member {
+                OsmId::Node(id) => (Relation_MemberType::NODE, id.0),
+                OsmId::Way(id) => (Relation_MemberType::WAY, id.0),
+                OsmId::Relation(id) => (Relation_MemberType::RELATION, id.0),
+            };
+            pbf_relation.mut_memids().push(id - last);
+            pbf_relation.mut_types().push(member_type);
+            last = id;
+        }
+
+        self.group.mut_relations().push(pbf_relation);
+        self.count += 1;
+    }
+
+    fn build(mut self) -> PrimitiveBlock {
+        if self.strings.is_empty() {
+            self.strings.push(String::new());
+        }
+        let mut string_table = StringTable::new();
+        for s in &self.strings {
+            string_table.mut_s().push(s.as_bytes().to_vec());
+        }
+
+        let mut block = PrimitiveBlock::new();
+        block.set_stringtable(string_table);
+        block.mut_primitivegroup().push(self.group);
+        block
+    }
+}
+
+fn to_nanodegrees(degrees: f64) -> i64 {
+    (degrees * 1e9).round() as i64
+}
+
+fn to_bytes(message: &dyn Message) -> Result<Vec<u8>> {
+    message.write_to_bytes().map_err(|e| Error::other(e.to_string()))
+}
+
+pub(crate) fn write_blob(output: &mut impl Write, blob_type: &str, data: &[u8]) -> Result<()> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+
+    let mut blob = Blob::new();
+    blob.set_raw_size(data.len() as i32);
+    blob.set_zlib_data(encoder.finish()?);
+    let blob_bytes = to_bytes(&blob)?;
+
+    let mut header = BlobHeader::new();
+    header.set_field_type(blob_type.to_string());
+    header.set_datasize(blob_bytes.len() as i32);
+    let header_bytes = to_bytes(&header)?;
+
+    output.write_all(&(header_bytes.len() as u32).to_be_bytes())?;
+    output.write_all(&header_bytes)?;
+    output.write_all(&blob_bytes)
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use osmpbfreader::{NodeId, OsmObj, OsmPbfReader, Ref, RelationId, WayId};
+    use smartstring::alias::String as SmartString;
+    use std::io::Cursor;
+    use std::iter::FromIterator;
+
+    fn create_tags(pairs: &[(&str, &str)]) -> Tags {
+        Tags::from_iter(
+            pairs
+                .iter()
+                .map(|(k, v)| (SmartString::from(*k), SmartString::from(*v))),
+        )
+    }
+
+    fn create_node(id: i64, lat: i32, lon: i32) -> Node {
+        Node {
+            id: NodeId(id),
+            tags: create_tags(&[]),
+            decimicro_lat: lat,
+            decimicro_lon: lon,
+        }
+    }
+
+    #[test]
+    fn test_written_file_can_be_read_again() {
+        let node1 = create_node(1, 490_000_000, 80_000_000);
+        let mut node2 = create_node(5, -100, 200);
+        node2.tags = create_tags(&[("place", "city")]);
+        let way = Way {
+            id: WayId(10),
+            tags: create_tags(&[("highway", "primary")]),
+            nodes: vec![NodeId(1), NodeId(5)],
+        };
+        let relation = Relation {
+            id: RelationId(100),
+            tags: create_tags(&[("type", "route")]),
+            refs: vec![
+                Ref {
+                    member: OsmId::Way(WayId(10)),
+                    role: SmartString::from("outer"),
+                },
+                Ref {
+                    member: OsmId::Node(NodeId(5)),
+                    role: SmartString::from("label"),
+                },
+            ],
+        };
+
+        let mut writer = PbfWriter::new(Vec::new(), None).unwrap();
+        writer.write_node(&node1).unwrap();
+        writer.write_node(&node2).unwrap();
+        writer.write_way(&way).unwrap();
+        writer.write_relation(&relation).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let mut reader = OsmPbfReader::new(Cursor::new(bytes));
+        let objects: Vec<OsmObj> = reader.iter().map(osmpbfreader::Result::unwrap).collect();
+
+        assert_eq!(
+            objects,
+            vec![
+                OsmObj::Node(node1),
+                OsmObj::Node(node2),
+                OsmObj::Way(way),
+                OsmObj::Relation(relation)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blocks_are_split_when_full() {
+        let mut writer = PbfWriter::new(Vec::new(), None).unwrap();
+        for id in 1..=(MAX_ENTITIES_PER_BLOCK as i64 + 1) {
+            writer.write_node(&create_node(id, 0, 0)).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+
+        let mut reader = OsmPbfReader::new(Cursor::new(bytes));
+        let blob_count = reader.primitive_blocks().count();
+
+        assert_eq!(blob_count, 2);
+    }
+}
@@ -0,0 +1,457 @@
+use crate::converter::{Point, Polygon};
+
+use geo::orient::Direction;
+use geo::{Area, BooleanOps, InteriorPoint, MapCoordsInPlace, Orient};
+use geo_types::{Coord, LineString, MultiPolygon, Polygon as GeoPolygon, Rect};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub min_lon: f64,
+    pub min_lat: f64,
+    pub max_lon: f64,
+    pub max_lat: f64,
+}
+
+impl BoundingBox {
+    pub fn of_polygon(polygon: &Polygon) -> Option<BoundingBox> {
+        BoundingBox::of_points(polygon.points.iter().flatten())
+    }
+
+    pub fn of_points<'a>(points: impl Iterator<Item = &'a Point>) -> Option<BoundingBox> {
+        BoundingBox::of_coordinates(points.map(|p| (p.lon as f64, p.lat as f64)))
+    }
+
+    /*
+        geographic bounding box as used by GeoJSON: for boundaries crossing the antimeridian
+        the box wraps around, i.e., min_lon (west) is bigger than max_lon (east)
+    */
+    pub fn of_polygon_wrapping(polygon: &Polygon) -> Option<BoundingBox> {
+        if !polygon.points.iter().any(|ring| crosses_antimeridian(ring)) {
+            return BoundingBox::of_polygon(polygon);
+        }
+
+        let shifted = polygon.points.iter().flatten().map(|p| {
+            let lon = p.lon as f64;
+            (if lon < 0.0 { lon + 360.0 } else { lon }, p.lat as f64)
+        });
+
+        BoundingBox::of_coordinates(shifted).map(|b| BoundingBox {
+            min_lon: normalize_lon(b.min_lon),
+            max_lon: normalize_lon(b.max_lon),
+            ..b
+        })
+    }
+
+    pub fn of_coordinates(coordinates: impl Iterator<Item = (f64, f64)>) -> Option<BoundingBox> {
+        coordinates.fold(None, |acc, (lon, lat)| {
+            Some(match acc {
+                None => BoundingBox {
+                    min_lon: lon,
+                    min_lat: lat,
+                    max_lon: lon,
+                    max_lat: lat,
+                },
+                Some(b) => BoundingBox {
+                    min_lon: b.min_lon.min(lon),
+                    min_lat: b.min_lat.min(lat),
+                    max_lon: b.max_lon.max(lon),
+                    max_lat: b.max_lat.max(lat),
+                },
+            })
+        })
+    }
+
+    pub fn contains(&self, lon: f64, lat: f64) -> bool {
+        self.min_lon <= lon && lon <= self.max_lon && self.min_lat <= lat && lat <= self.max_lat
+    }
+
+    pub fn intersects(&self, other: &BoundingBox) -> bool {
+        self.min_lon <= other.max_lon
+            && other.min_lon <= self.max_lon
+            && self.min_lat <= other.max_lat
+            && other.min_lat <= self.max_lat
+    }
+
+    pub fn covers(&self, other: &BoundingBox) -> bool {
+        // boundaries sharing a border have (almost) the same extent in that direction
+        const EPSILON: f64 = 1e-5;
+        self.min_lon - EPSILON <= other.min_lon
+            && other.max_lon <= self.max_lon + EPSILON
+            && self.min_lat - EPSILON <= other.min_lat
+            && other.max_lat <= self.max_lat + EPSILON
+    }
+}
+
+// a ring crosses the antimeridian if it contains an edge spanning more than half of the globe
+pub fn crosses_antimeridian(ring: &[Point]) -> bool {
+    ring.windows(2)
+        .any(|w| (w[1].lon as f64 - w[0].lon as f64).abs() > 180.0)
+}
+
+pub fn normalize_lon(lon: f64) -> f64 {
+    if lon > 180.0 {
+        lon - 360.0
+    } else {
+        lon
+    }
+}
+
+pub fn polygon_contains(polygon: &Polygon, lon: f64, lat: f64) -> bool {
+    /*
+        even-odd rule over all rings of the polygon: a point that lies inside of
+        an odd number of rings is inside, i.e., inner rings are treated as holes
+    */
+    polygon
+        .points
+        .iter()
+        .filter(|ring| ring_contains(ring, lon, lat))
+        .count()
+        % 2
+        == 1
+}
+
+pub fn to_multi_polygon(polygon: &Polygon) -> MultiPolygon<f64> {
+    /*
+        the rings of a boundary are not tagged as outer or inner ring, hence the
+        structure is derived from the nesting of the rings:
+         - a ring that is contained in an even number of other rings is an outer ring
+         - every other ring is a hole of the smallest ring containing it
+        the rings are oriented as required by GeoJSON (outer rings counter-clockwise)
+    */
+    let rings: Vec<LineString<f64>> = polygon
+        .points
+        .iter()
+        .filter(|r| r.len() >= 3)
+        .map(|r| to_linestring(r))
+        .collect();
+
+    nest_rings(rings)
+}
+
+fn nest_rings(rings: Vec<LineString<f64>>) -> MultiPolygon<f64> {
+    let areas: Vec<f64> = rings
+        .iter()
+        .map(|r| GeoPolygon::new(r.clone(), vec![]).unsigned_area())
+        .collect();
+
+    let containing: Vec<Vec<usize>> = rings
+        .iter()
+        .enumerate()
+        .map(|(i, ring)| {
+            let probe = ring.0[0];
+            (0..rings.len())
+                .filter(|&j| j != i && areas[j] > areas[i])
+                .filter(|&j| {
+                    ray_cast(
+                        rings[j].0.len(),
+                        |k| (rings[j].0[k].x, rings[j].0[k].y),
+                        probe.x,
+                        probe.y,
+                    )
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut outers: Vec<(usize, Vec<LineString<f64>>)> = Vec::new();
+    let mut holes: Vec<(usize, LineString<f64>)> = Vec::new();
+    for (i, ring) in rings.iter().enumerate() {
+        if containing[i].len().is_multiple_of(2) {
+            outers.push((i, Vec::new()));
+        } else {
+            let parent = *containing[i]
+                .iter()
+                .min_by(|a, b| areas[**a].total_cmp(&areas[**b]))
+                .unwrap();
+            holes.push((parent, ring.clone()));
+        }
+    }
+
+    for (parent, hole) in holes {
+        if let Some((_, interiors)) = outers.iter_mut().find(|(i, _)| *i == parent) {
+            interiors.push(hole);
+        }
+    }
+
+    MultiPolygon(
+        outers
+            .into_iter()
+            .map(|(i, interiors)| GeoPolygon::new(rings[i].clone(), interiors))
+            .collect(),
+    )
+    .orient(Direction::Default)
+}
+
+// like to_multi_polygon, but boundaries crossing the antimeridian have their negative longitudes moved behind 180°
+pub fn to_continuous_multi_polygon(polygon: &Polygon) -> MultiPolygon<f64> {
+    if !polygon.points.iter().any(|ring| crosses_antimeridian(ring)) {
+        return to_multi_polygon(polygon);
+    }
+
+    let rings: Vec<LineString<f64>> = polygon
+        .points
+        .iter()
+        .filter(|r| r.len() >= 3)
+        .map(|r| {
+            let mut linestring = to_linestring(r);
+            linestring.map_coords_in_place(|c| Coord {
+                x: if c.x < 0.0 { c.x + 360.0 } else { c.x },
+                y: c.y,
+            });
+            linestring
+        })
+        .collect();
+
+    nest_rings(rings)
+}
+
+pub fn split_at_antimeridian(polygon: &Polygon) -> MultiPolygon<f64> {
+    /*
+        boundaries crossing the antimeridian are split into a part east and a part west of it,
+        as recommended by RFC 7946; all other boundaries are returned as by to_multi_polygon
+         1. negative longitudes are moved behind 180°, so that every ring is continuous
+         2. the result is intersected with both hemispheres, and the western one is moved back
+    */
+    if !polygon.points.iter().any(|ring| crosses_antimeridian(ring)) {
+        return to_multi_polygon(polygon);
+    }
+
+    let shifted = to_continuous_multi_polygon(polygon);
+
+    let east =
+        shifted.intersection(&Rect::new(Coord { x: -180.0, y: -90.0 }, Coord { x: 180.0, y: 90.0 }).to_polygon());
+    let mut west =
+        shifted.intersection(&Rect::new(Coord { x: 180.0, y: -90.0 }, Coord { x: 540.0, y: 90.0 }).to_polygon());
+    west.map_coords_in_place(|c| Coord { x: c.x - 360.0, y: c.y });
+
+    MultiPolygon(east.0.into_iter().chain(west.0).collect()).orient(Direction::Default)
+}
+
+pub fn interior_point(polygon: &Polygon) -> Option<(f64, f64)> {
+    to_multi_polygon(polygon).interior_point().map(|p| (p.x(), p.y()))
+}
+
+fn to_linestring(ring: &[Point]) -> LineString<f64> {
+    let mut linestring = LineString(
+        ring.iter()
+            .map(|p| Coord {
+                x: p.lon as f64,
+                y: p.lat as f64,
+            })
+            .collect(),
+    );
+    linestring.close();
+    linestring
+}
+
+pub fn ring_contains(ring: &[Point], lon: f64, lat: f64) -> bool {
+    ray_cast(ring.len(), |i| (ring[i].lon as f64, ring[i].lat as f64), lon, lat)
+}
+
+fn ray_cast(n: usize, coordinate: impl Fn(usize) -> (f64, f64), lon: f64, lat: f64) -> bool {
+    let mut inside = false;
+    if n < 3 {
+        return false;
+    }
+
+    let mut j = n - 1;
+    for i in 0..n {
+        let (xi, yi) = coordinate(i);
+        let (xj, yj) = coordinate(j);
+        if (yi > lat) != (yj > lat) && lon < (xj - xi) * (lat - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+// closed counter-clockwise ring from (min, min) to (max, max), shared by the tests of several modules
+#[cfg(test)]
+pub fn square_ring(min: f32, max: f32) -> Vec<Point> {
+    vec![
+        Point { lat: min, lon: min },
+        Point { lat: min, lon: max },
+        Point { lat: max, lon: max },
+        Point { lat: max, lon: min },
+        Point { lat: min, lon: min },
+    ]
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_polygon(rings: Vec<Vec<Point>>) -> Polygon {
+        Polygon {
+            name: "barfoo".to_string(),
+            points: rings,
+            relation_id: 1,
+            admin_level: 8,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_bounding_box_of_empty_polygon_is_none() {
+        assert_eq!(BoundingBox::of_polygon(&create_polygon(vec![])), None);
+    }
+
+    #[test]
+    fn test_bounding_box_covers_all_rings() {
+        let polygon = create_polygon(vec![square_ring(0.0, 1.0), square_ring(5.0, 7.0)]);
+
+        let result = BoundingBox::of_polygon(&polygon).unwrap();
+
+        assert_eq!(
+            result,
+            BoundingBox {
+                min_lon: 0.0,
+                min_lat: 0.0,
+                max_lon: 7.0,
+                max_lat: 7.0
+            }
+        );
+    }
+
+    #[test]
+    fn test_bounding_box_wraps_at_antimeridian() {
+        let ring = vec![
+            Point { lat: -17.0, lon: 179.0 },
+            Point {
+                lat: -17.0,
+                lon: -179.5,
+            },
+            Point {
+                lat: -16.0,
+                lon: -179.5,
+            },
+            Point { lat: -16.0, lon: 179.0 },
+            Point { lat: -17.0, lon: 179.0 },
+        ];
+        let polygon = create_polygon(vec![ring.clone()]);
+
+        assert!(crosses_antimeridian(&ring));
+        assert_eq!(
+            BoundingBox::of_polygon_wrapping(&polygon).unwrap(),
+            BoundingBox {
+                min_lon: 179.0,
+                min_lat: -17.0,
+                max_lon: -179.5,
+                max_lat: -16.0
+            }
+        );
+    }
+
+    #[test]
+    fn test_bounding_box_does_not_wrap_for_other_polygons() {
+        let polygon = create_polygon(vec![square_ring(0.0, 1.0)]);
+
+        assert!(!crosses_antimeridian(&polygon.points[0]));
+        assert_eq!(
+            BoundingBox::of_polygon_wrapping(&polygon),
+            BoundingBox::of_polygon(&polygon)
+        );
+    }
+
+    #[test]
+    fn test_split_at_antimeridian_keeps_holes_and_area() {
+        let ring = |s: f32, n: f32, w: f32, e: f32| {
+            vec![
+                Point { lat: s, lon: w },
+                Point { lat: s, lon: e },
+                Point { lat: n, lon: e },
+                Point { lat: n, lon: w },
+                Point { lat: s, lon: w },
+            ]
+        };
+        let polygon = create_polygon(vec![
+            ring(-20.0, -10.0, 170.0, -170.0),
+            ring(-16.0, -14.0, -175.0, -172.0),
+        ]);
+
+        let result = split_at_antimeridian(&polygon);
+
+        assert_eq!(result.0.len(), 2);
+        assert_eq!(result.0.iter().map(|p| p.interiors().len()).sum::<usize>(), 1);
+        assert!((result.unsigned_area() - (200.0 - 6.0)).abs() < 1e-6);
+        let bbox = BoundingBox::of_coordinates(result.0.iter().flat_map(|p| p.exterior().0.iter().map(|c| (c.x, c.y))));
+        assert_eq!((bbox.unwrap().min_lon, bbox.unwrap().max_lon), (-180.0, 180.0));
+    }
+
+    #[test]
+    fn test_split_at_antimeridian_keeps_other_polygons() {
+        let polygon = create_polygon(vec![square_ring(0.0, 10.0), square_ring(4.0, 6.0)]);
+
+        assert_eq!(split_at_antimeridian(&polygon), to_multi_polygon(&polygon));
+    }
+
+    #[test]
+    fn test_polygon_contains_point_inside_outer_ring() {
+        let polygon = create_polygon(vec![square_ring(0.0, 10.0)]);
+        assert!(polygon_contains(&polygon, 5.0, 5.0));
+        assert!(!polygon_contains(&polygon, 15.0, 5.0));
+    }
+
+    #[test]
+    fn test_to_multi_polygon_assigns_holes_to_smallest_containing_ring() {
+        let polygon = create_polygon(vec![
+            square_ring(0.0, 10.0),
+            square_ring(4.0, 6.0),
+            square_ring(20.0, 30.0),
+        ]);
+
+        let result = to_multi_polygon(&polygon);
+
+        assert_eq!(result.0.len(), 2);
+        assert_eq!(result.0[0].interiors().len(), 1);
+        assert_eq!(result.0[1].interiors().len(), 0);
+    }
+
+    #[test]
+    fn test_to_multi_polygon_orients_outer_rings_counter_clockwise_and_holes_clockwise() {
+        let mut outer = square_ring(0.0, 10.0);
+        outer.reverse();
+        let polygon = create_polygon(vec![outer, square_ring(4.0, 6.0)]);
+
+        let result = to_multi_polygon(&polygon);
+
+        assert!(result.0[0].signed_area() > 0.0);
+        assert_eq!(result.unsigned_area(), 96.0);
+    }
+
+    #[test]
+    fn test_to_multi_polygon_treats_island_in_hole_as_outer_ring() {
+        let polygon = create_polygon(vec![
+            square_ring(0.0, 10.0),
+            square_ring(2.0, 8.0),
+            square_ring(4.0, 6.0),
+        ]);
+
+        let result = to_multi_polygon(&polygon);
+
+        assert_eq!(result.0.len(), 2);
+    }
+
+    #[test]
+    fn test_interior_point_avoids_holes() {
+        let polygon = create_polygon(vec![square_ring(0.0, 10.0), square_ring(1.0, 9.0)]);
+
+        let (lon, lat) = interior_point(&polygon).unwrap();
+
+        assert!(polygon_contains(&polygon, lon, lat));
+    }
+
+    #[test]
+    fn test_polygon_does_not_contain_point_inside_hole() {
+        let polygon = create_polygon(vec![square_ring(0.0, 10.0), square_ring(4.0, 6.0)]);
+        assert!(!polygon_contains(&polygon, 5.0, 5.0));
+        assert!(polygon_contains(&polygon, 2.0, 2.0));
+    }
+}
@@ -0,0 +1,209 @@
+use crate::converter::{Ancestor, Polygon};
+use crate::geometry::{interior_point, BoundingBox};
+use crate::spatial_index::BoundaryIndex;
+
+use serde_json::json;
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+pub fn assign_ancestors(polygons: &mut [Polygon]) {
+    let now = Instant::now();
+    println!("computing boundary hierarchy...");
+
+    let parents = find_parents(polygons);
+    let by_id: HashMap<i64, &Polygon> = polygons.iter().map(|p| (p.relation_id, p)).collect();
+
+    let ancestors: Vec<Vec<Ancestor>> = polygons
+        .iter()
+        .map(|polygon| {
+            let mut chain = Vec::new();
+            let mut current = parents.get(&polygon.relation_id);
+            while let Some(parent) = current.and_then(|id| by_id.get(id)) {
+                chain.push(Ancestor {
+                    relation_id: parent.relation_id,
+                    name: parent.name.clone(),
+                    admin_level: parent.admin_level,
+                });
+                current = parents.get(&parent.relation_id);
+            }
+            chain.reverse();
+            chain
+        })
+        .collect();
+
+    for (polygon, chain) in polygons.iter_mut().zip(ancestors) {
+        polygon.ancestors = chain;
+    }
+
+    println!(
+        "finished computing boundary hierarchy! {} of {} boundaries have a parent. {}s",
+        parents.len(),
+        polygons.len(),
+        now.elapsed().as_secs()
+    );
+}
+
+fn find_parents(polygons: &[Polygon]) -> HashMap<i64, i64> {
+    /*
+        the parent of a boundary is the boundary with the next lower admin level containing it:
+         1. if a boundary is referenced as subarea by another boundary, that one is used
+         2. otherwise, the boundaries containing an interior point of the boundary are
+            candidates, as long as their bounding box covers the one of the boundary
+
+        since the admin level strictly decreases from child to parent there are no cycles
+    */
+    let by_id: HashMap<i64, &Polygon> = polygons.iter().map(|p| (p.relation_id, p)).collect();
+    let mut parents: HashMap<i64, i64> = HashMap::new();
+
+    for parent in polygons {
+        for child in parent.subarea_ids.iter().filter_map(|id| by_id.get(id)) {
+            if parent.admin_level >= child.admin_level {
+                continue;
+            }
+            let closer = match parents.get(&child.relation_id).and_then(|id| by_id.get(id)) {
+                Some(previous) => previous.admin_level < parent.admin_level,
+                None => true,
+            };
+            if closer {
+                parents.insert(child.relation_id, parent.relation_id);
+            }
+        }
+    }
+
+    let index = BoundaryIndex::new(polygons);
+    for polygon in polygons {
+        if parents.contains_key(&polygon.relation_id) {
+            continue;
+        }
+        if let Some(parent) = find_geometric_parent(&index, polygon) {
+            parents.insert(polygon.relation_id, parent.relation_id);
+        }
+    }
+
+    parents
+}
+
+fn find_geometric_parent<'a>(index: &BoundaryIndex<'a>, polygon: &Polygon) -> Option<&'a Polygon> {
+    let (lon, lat) = interior_point(polygon)?;
+    let bbox = BoundingBox::of_polygon(polygon)?;
+
+    // lookup returns the candidates sorted by admin level, the closest one is the last
+    index
+        .lookup(lon, lat)
+        .into_iter()
+        .filter(|candidate| candidate.admin_level < polygon.admin_level)
+        .rfind(|candidate| BoundingBox::of_polygon(candidate).is_some_and(|b| b.covers(&bbox)))
+}
+
+pub fn render_tree(polygons: &[Polygon]) -> serde_json::Value {
+    let mut children: HashMap<Option<i64>, Vec<&Polygon>> = HashMap::new();
+    for polygon in polygons {
+        children.entry(polygon.parent_relation_id()).or_default().push(polygon);
+    }
+    for list in children.values_mut() {
+        list.sort_by(|a, b| (a.admin_level, &a.name).cmp(&(b.admin_level, &b.name)));
+    }
+
+    serde_json::Value::Array(render_children(None, &children))
+}
+
+fn render_children(parent: Option<i64>, children: &HashMap<Option<i64>, Vec<&Polygon>>) -> Vec<serde_json::Value> {
+    children
+        .get(&parent)
+        .map(|list| {
+            list.iter()
+                .map(|p| {
+                    json!({
+                        "relation_id": p.relation_id,
+                        "name": p.name,
+                        "admin_level": p.admin_level,
+                        "children": render_children(Some(p.relation_id), children),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::Point;
+    use crate::geometry::square_ring;
+
+    fn create_polygon(relation_id: i64, admin_level: i64, ring: Vec<Point>, subarea_ids: Vec<i64>) -> Polygon {
+        Polygon {
+            name: format!("polygon_{}", relation_id),
+            points: vec![ring],
+            relation_id,
+            admin_level,
+            subarea_ids,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_assign_ancestors_uses_geometric_containment() {
+        let mut polygons = vec![
+            create_polygon(3, 8, square_ring(1.0, 2.0), vec![]),
+            create_polygon(1, 2, square_ring(0.0, 10.0), vec![]),
+            create_polygon(2, 4, square_ring(0.0, 5.0), vec![]),
+        ];
+
+        assign_ancestors(&mut polygons);
+
+        let chain: Vec<i64> = polygons[0].ancestors.iter().map(|a| a.relation_id).collect();
+        assert_eq!(chain, vec![1, 2]);
+        assert_eq!(polygons[0].parent_relation_id(), Some(2));
+        assert_eq!(polygons[1].parent_relation_id(), None);
+    }
+
+    #[test]
+    fn test_assign_ancestors_prefers_subarea_members() {
+        // geometrically, 3 lies within 2, but 1 explicitly lists it as subarea
+        let mut polygons = vec![
+            create_polygon(1, 4, square_ring(0.0, 10.0), vec![3]),
+            create_polygon(2, 6, square_ring(0.0, 5.0), vec![]),
+            create_polygon(3, 8, square_ring(1.0, 2.0), vec![]),
+        ];
+
+        assign_ancestors(&mut polygons);
+
+        assert_eq!(polygons[2].parent_relation_id(), Some(1));
+    }
+
+    #[test]
+    fn test_assign_ancestors_ignores_subareas_with_lower_admin_level() {
+        let mut polygons = vec![
+            create_polygon(1, 8, square_ring(0.0, 1.0), vec![2]),
+            create_polygon(2, 4, square_ring(0.0, 10.0), vec![]),
+        ];
+
+        assign_ancestors(&mut polygons);
+
+        assert_eq!(polygons[1].parent_relation_id(), None);
+        assert_eq!(polygons[0].parent_relation_id(), Some(2));
+    }
+
+    #[test]
+    fn test_render_tree_nests_children() {
+        let mut polygons = vec![
+            create_polygon(1, 2, square_ring(0.0, 10.0), vec![]),
+            create_polygon(2, 4, square_ring(0.0, 5.0), vec![]),
+        ];
+        assign_ancestors(&mut polygons);
+
+        let result = render_tree(&polygons);
+
+        assert_eq!(result[0]["relation_id"], 1);
+        assert_eq!(result[0]["children"][0]["relation_id"], 2);
+        assert_eq!(result[0]["children"][0]["children"].as_array().unwrap().len(), 0);
+    }
+}
@@ -0,0 +1,429 @@
+use crate::converter;
+use crate::geometry::BoundingBox;
+use crate::osm_reader::{self, RelationFilter, RelationNodes};
+use crate::output::file_creator::FileCreator;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use osmpbfreader::{Node, NodeId, OsmId, Ref, Relation, RelationId, Tags, WayId};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use serde_json::{Map, Value};
+
+use std::collections::HashSet;
+use std::io::prelude::*;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+// increased whenever the layout of the tables or of the encoded data changes
+const FORMAT_VERSION: &str = "1";
+
+/*
+    the index is an SQLite database with one row per boundary relation: its tags as a json object, the extent
+    of its nodes, and the gzipped members, rings, and admin_centre and label nodes; the rings are stored merged,
+    so reading them again neither needs the input file nor the assembly of the ways
+*/
+
+pub fn write(
+    filename: &str,
+    source: &str,
+    relations: &[RelationNodes],
+    file_creator: &mut FileCreator,
+) -> Result<usize> {
+    // asks before an existing file is replaced, the database is then created in the emptied file
+    drop(file_creator.create_file(Path::new(filename))?);
+
+    let connection = Connection::open(filename).map_err(to_io_error)?;
+    connection
+        .execute_batch(
+            "CREATE TABLE metadata (name TEXT PRIMARY KEY, value TEXT);
+             CREATE TABLE boundaries (
+                 relation_id INTEGER PRIMARY KEY,
+                 min_lon REAL, min_lat REAL, max_lon REAL, max_lat REAL,
+                 tags TEXT NOT NULL,
+                 data BLOB NOT NULL
+             );
+             BEGIN;",
+        )
+        .map_err(to_io_error)?;
+
+    for relation_nodes in relations {
+        let merged = converter::merge_nodes(relation_nodes);
+        let bbox = BoundingBox::of_coordinates(merged.nodes.iter().flatten().map(|node| (node.lon(), node.lat())));
+        let tags: Map<String, Value> = merged
+            .relation
+            .tags
+            .iter()
+            .map(|(k, v)| (k.to_string(), Value::from(v.as_str())))
+            .collect();
+
+        connection
+            .execute(
+                "INSERT INTO boundaries (relation_id, min_lon, min_lat, max_lon, max_lat, tags, data)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    merged.relation.id.0,
+                    bbox.as_ref().map(|b| b.min_lon),
+                    bbox.as_ref().map(|b| b.min_lat),
+                    bbox.as_ref().map(|b| b.max_lon),
+                    bbox.as_ref().map(|b| b.max_lat),
+                    Value::Object(tags).to_string(),
+                    compress(&encode(&merged))?,
+                ],
+            )
+            .map_err(to_io_error)?;
+    }
+
+    let metadata = [
+        ("format", FORMAT_VERSION.to_string()),
+        ("source", source.to_string()),
+        (
+            "generator",
+            format!("osm_extract_polygon {}", env!("CARGO_PKG_VERSION")),
+        ),
+    ];
+    for (name, value) in metadata.iter() {
+        connection
+            .execute(
+                "INSERT INTO metadata (name, value) VALUES (?1, ?2)",
+                params![name, value],
+            )
+            .map_err(to_io_error)?;
+    }
+    connection.execute_batch("COMMIT;").map_err(to_io_error)?;
+
+    Ok(relations.len())
+}
+
+pub fn read(filename: &str, filter: &RelationFilter) -> Result<Vec<RelationNodes>> {
+    let connection = Connection::open_with_flags(filename, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(to_io_error)?;
+    let format: Option<String> = connection
+        .query_row("SELECT value FROM metadata WHERE name = 'format'", [], |row| row.get(0))
+        .optional()
+        .map_err(|_| invalid_data(format!("{} is not an index written by build-index", filename)))?;
+    if format.as_deref() != Some(FORMAT_VERSION) {
+        return Err(invalid_data(format!(
+            "{} was written by another version, run build-index again",
+            filename
+        )));
+    }
+
+    let mut statement = connection
+        .prepare("SELECT relation_id, min_lon, min_lat, max_lon, max_lat, tags, data FROM boundaries")
+        .map_err(to_io_error)?;
+    let mut rows = statement.query([]).map_err(to_io_error)?;
+
+    let mut relations = Vec::new();
+    while let Some(row) = rows.next().map_err(to_io_error)? {
+        let relation_id = RelationId(row.get(0).map_err(to_io_error)?);
+        let bbox: Option<BoundingBox> = match (row.get(1), row.get(2), row.get(3), row.get(4)) {
+            (Ok(Some(min_lon)), Ok(Some(min_lat)), Ok(Some(max_lon)), Ok(Some(max_lat))) => Some(BoundingBox {
+                min_lon,
+                min_lat,
+                max_lon,
+                max_lat,
+            }),
+            _ => None,
+        };
+        let tags = parse_tags(&row.get::<_, String>(5).map_err(to_io_error)?)?;
+
+        // the tags and the extent are enough to decide whether the encoded data is needed at all
+        let relation = Relation {
+            id: relation_id,
+            tags,
+            refs: Vec::new(),
+        };
+        let in_region = filter
+            .region
+            .as_ref()
+            .is_none_or(|region| bbox.as_ref().is_some_and(|bbox| region.may_select(bbox)));
+        if !filter.matches(&relation) || !in_region {
+            continue;
+        }
+
+        let data = decompress(&row.get::<_, Vec<u8>>(6).map_err(to_io_error)?)?;
+        relations
+            .push(decode(relation, &data).map_err(|e| invalid_data(format!("relation {}: {}", relation_id.0, e)))?);
+    }
+
+    let found: HashSet<RelationId> = relations.iter().map(|r| r.relation.id).collect();
+    osm_reader::report_missing_relation_ids(filter, |id| found.contains(id));
+    Ok(relations)
+}
+
+fn parse_tags(text: &str) -> Result<Tags> {
+    let object: Map<String, Value> = serde_json::from_str(text).map_err(|e| invalid_data(e.to_string()))?;
+    let mut tags = Tags::new();
+    for (key, value) in object {
+        tags.insert(key.into(), value.as_str().unwrap_or_default().into());
+    }
+    Ok(tags)
+}
+
+fn encode(relation_nodes: &RelationNodes) -> Vec<u8> {
+    /*
+        all numbers are varints, signed ones zigzag encoded; ids and coordinates are stored as the difference
+        to the previous one: the members (type, id, role), the rings (length, then id, lat, lon per node),
+        and the member nodes (role, id, lat, lon, tags)
+    */
+    let mut buf = Vec::new();
+
+    let refs = &relation_nodes.relation.refs;
+    write_varint(&mut buf, refs.len() as u64);
+    let mut last_id = 0;
+    for r in refs {
+        let (member_type, id) = match r.member {
+            OsmId::Node(id) => (0, id.0),
+            OsmId::Way(id) => (1, id.0),
+            OsmId::Relation(id) => (2, id.0),
+        };
+        write_varint(&mut buf, member_type);
+        write_signed(&mut buf, id - last_id);
+        write_string(&mut buf, &r.role);
+        last_id = id;
+    }
+
+    write_varint(&mut buf, relation_nodes.nodes.len() as u64);
+    let (mut last_id, mut last_lat, mut last_lon) = (0, 0, 0);
+    for ring in &relation_nodes.nodes {
+        write_varint(&mut buf, ring.len() as u64);
+        for node in ring {
+            write_signed(&mut buf, node.id.0 - last_id);
+            write_signed(&mut buf, node.decimicro_lat as i64 - last_lat);
+            write_signed(&mut buf, node.decimicro_lon as i64 - last_lon);
+            last_id = node.id.0;
+            last_lat = node.decimicro_lat as i64;
+            last_lon = node.decimicro_lon as i64;
+        }
+    }
+
+    write_varint(&mut buf, relation_nodes.member_nodes.len() as u64);
+    for (role, node) in &relation_nodes.member_nodes {
+        write_string(&mut buf, role);
+        write_signed(&mut buf, node.id.0);
+        write_signed(&mut buf, node.decimicro_lat as i64);
+        write_signed(&mut buf, node.decimicro_lon as i64);
+        write_varint(&mut buf, node.tags.len() as u64);
+        for (key, value) in node.tags.iter() {
+            write_string(&mut buf, key);
+            write_string(&mut buf, value);
+        }
+    }
+
+    buf
+}
+
+fn decode(mut relation: Relation, data: &[u8]) -> std::result::Result<RelationNodes, String> {
+    let mut decoder = Decoder { data, position: 0 };
+
+    let mut last_id = 0;
+    for _ in 0..decoder.varint()? {
+        let member_type = decoder.varint()?;
+        let id = last_id + decoder.signed()?;
+        let member = match member_type {
+            0 => OsmId::Node(NodeId(id)),
+            1 => OsmId::Way(WayId(id)),
+            2 => OsmId::Relation(RelationId(id)),
+            _ => return Err(format!("unknown member type {}", member_type)),
+        };
+        relation.refs.push(Ref {
+            member,
+            role: decoder.string()?.into(),
+        });
+        last_id = id;
+    }
+
+    let mut nodes = Vec::new();
+    let (mut last_id, mut last_lat, mut last_lon) = (0, 0, 0);
+    for _ in 0..decoder.varint()? {
+        let mut ring = Vec::new();
+        for _ in 0..decoder.varint()? {
+            last_id += decoder.signed()?;
+            last_lat += decoder.signed()?;
+            last_lon += decoder.signed()?;
+            ring.push(Node {
+                id: NodeId(last_id),
+                tags: Tags::new(),
+                decimicro_lat: last_lat as i32,
+                decimicro_lon: last_lon as i32,
+            });
+        }
+        nodes.push(ring);
+    }
+
+    let mut member_nodes = Vec::new();
+    for _ in 0..decoder.varint()? {
+        let role = decoder.string()?;
+        let mut node = Node {
+            id: NodeId(decoder.signed()?),
+            decimicro_lat: decoder.signed()? as i32,
+            decimicro_lon: decoder.signed()? as i32,
+            tags: Tags::new(),
+        };
+        for _ in 0..decoder.varint()? {
+            let key = decoder.string()?;
+            node.tags.insert(key.into(), decoder.string()?.into());
+        }
+        member_nodes.push((role, node));
+    }
+
+    Ok(RelationNodes {
+        relation,
+        nodes,
+        member_nodes,
+    })
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_signed(buf: &mut Vec<u8>, value: i64) {
+    write_varint(buf, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn write_string(buf: &mut Vec<u8>, value: &str) {
+    write_varint(buf, value.len() as u64);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Decoder<'_> {
+    fn varint(&mut self) -> std::result::Result<u64, String> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.data.get(self.position).ok_or("unexpected end of data")?;
+            self.position += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte < 0x80 {
+                return Ok(value);
+            }
+        }
+        Err(String::from("invalid varint"))
+    }
+
+    fn signed(&mut self) -> std::result::Result<i64, String> {
+        let value = self.varint()?;
+        Ok(((value >> 1) as i64) ^ -((value & 1) as i64))
+    }
+
+    fn string(&mut self) -> std::result::Result<String, String> {
+        let length = self.varint()? as usize;
+        let bytes = self
+            .data
+            .get(self.position..self.position + length)
+            .ok_or("unexpected end of data")?;
+        self.position += length;
+        String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string())
+    }
+}
+
+fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut result = Vec::new();
+    GzDecoder::new(data).read_to_end(&mut result)?;
+    Ok(result)
+}
+
+fn invalid_data(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn to_io_error(e: rusqlite::Error) -> Error {
+    Error::other(e.to_string())
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: i64, lat: i32, lon: i32) -> Node {
+        Node {
+            id: NodeId(id),
+            tags: Tags::new(),
+            decimicro_lat: lat,
+            decimicro_lon: lon,
+        }
+    }
+
+    #[test]
+    fn test_encoded_relation_can_be_decoded_again() {
+        let mut tags = Tags::new();
+        tags.insert("name".into(), "Łódź".into());
+        let mut centre = node(7, 517_600_000, 194_600_000);
+        centre.tags.insert("place".into(), "city".into());
+        let relation = Relation {
+            id: RelationId(3),
+            tags,
+            refs: vec![
+                Ref {
+                    member: OsmId::Way(WayId(5)),
+                    role: "outer".into(),
+                },
+                Ref {
+                    member: OsmId::Relation(RelationId(1)),
+                    role: "subarea".into(),
+                },
+                Ref {
+                    member: OsmId::Node(NodeId(7)),
+                    role: "admin_centre".into(),
+                },
+            ],
+        };
+        let relation_nodes = RelationNodes {
+            relation: relation.clone(),
+            nodes: vec![
+                vec![node(1, 0, 0), node(2, -10, 1_800_000_000), node(1, 0, 0)],
+                vec![node(900, -900_000_000, -1_800_000_000)],
+            ],
+            member_nodes: vec![(String::from("admin_centre"), centre)],
+        };
+
+        let without_refs = Relation {
+            refs: Vec::new(),
+            ..relation
+        };
+        let decoded = decode(without_refs, &encode(&relation_nodes)).unwrap();
+
+        assert_eq!(decoded.relation, relation_nodes.relation);
+        assert_eq!(decoded.nodes, relation_nodes.nodes);
+        assert_eq!(decoded.member_nodes, relation_nodes.member_nodes);
+    }
+
+    #[test]
+    fn test_truncated_data_is_an_error() {
+        let relation_nodes = RelationNodes {
+            relation: Relation {
+                id: RelationId(1),
+                tags: Tags::new(),
+                refs: Vec::new(),
+            },
+            nodes: vec![vec![node(1, 0, 0), node(2, 10, 10)]],
+            member_nodes: Vec::new(),
+        };
+        let data = encode(&relation_nodes);
+
+        let result = decode(relation_nodes.relation.clone(), &data[..data.len() - 1]);
+
+        assert_eq!(result.err(), Some(String::from("unexpected end of data")));
+    }
+}
@@ -0,0 +1,125 @@
+use crate::converter::Polygon;
+use crate::spatial_index::BoundaryIndex;
+
+use serde_json::json;
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Result};
+use std::path::Path;
+
+pub enum QuerySource {
+    Arguments(Vec<String>),
+    Csv(String),
+    Stdin,
+}
+
+pub fn run(index: &BoundaryIndex, source: QuerySource) -> Result<u64> {
+    match source {
+        QuerySource::Arguments(points) => Ok(answer_queries(index, points.into_iter())),
+        QuerySource::Csv(filename) => {
+            let file = File::open(Path::new(&filename))?;
+            let lines = BufReader::new(file).lines().collect::<Result<Vec<String>>>()?;
+            Ok(answer_queries(index, lines.into_iter()))
+        }
+        QuerySource::Stdin => {
+            let lines = io::stdin().lock().lines().map_while(Result::ok);
+            Ok(answer_queries(index, lines))
+        }
+    }
+}
+
+fn answer_queries(index: &BoundaryIndex, queries: impl Iterator<Item = String>) -> u64 {
+    let mut count = 0;
+    for (line_number, query) in queries.enumerate() {
+        if query.trim().is_empty() {
+            continue;
+        }
+        match parse_query(&query) {
+            Some((lat, lon)) => {
+                println!("{}", format_result(lat, lon, &index.lookup(lon, lat)));
+                count += 1;
+            }
+            // a header line of a csv file is silently skipped
+            None if line_number == 0 && !query.trim_start().starts_with(|c: char| c.is_ascii_digit() || c == '-') => {}
+            None => eprintln!("error: cannot parse '{}' as lat,lon", query.trim()),
+        }
+    }
+    count
+}
+
+fn parse_query(query: &str) -> Option<(f64, f64)> {
+    let mut fields = query
+        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter(|f| !f.is_empty());
+    let lat = fields.next()?.trim().parse::<f64>().ok()?;
+    let lon = fields.next()?.trim().parse::<f64>().ok()?;
+
+    if (-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon) {
+        Some((lat, lon))
+    } else {
+        None
+    }
+}
+
+fn format_result(lat: f64, lon: f64, polygons: &[&Polygon]) -> String {
+    let hierarchy: Vec<serde_json::Value> = polygons
+        .iter()
+        .map(|p| {
+            json!({
+                "admin_level": p.admin_level,
+                "name": p.name,
+                "relation_id": p.relation_id,
+            })
+        })
+        .collect();
+
+    json!({ "lat": lat, "lon": lon, "hierarchy": hierarchy }).to_string()
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query_reads_lat_before_lon() {
+        assert_eq!(parse_query("49.01,8.40"), Some((49.01, 8.40)));
+        assert_eq!(parse_query(" -33.9 ; 18.4 "), Some((-33.9, 18.4)));
+        assert_eq!(parse_query("52.5 13.4"), Some((52.5, 13.4)));
+    }
+
+    #[test]
+    fn test_parse_query_ignores_additional_csv_columns() {
+        assert_eq!(parse_query("49.01,8.40,Karlsruhe"), Some((49.01, 8.40)));
+    }
+
+    #[test]
+    fn test_parse_query_rejects_invalid_input() {
+        assert_eq!(parse_query("lat,lon"), None);
+        assert_eq!(parse_query("49.01"), None);
+        assert_eq!(parse_query("91.0,8.0"), None);
+    }
+
+    #[test]
+    fn test_format_result_lists_hierarchy() {
+        let polygon = Polygon {
+            name: "barfoo".to_string(),
+            points: vec![],
+            relation_id: 42,
+            admin_level: 8,
+            ..Default::default()
+        };
+
+        let result = format_result(1.0, 2.0, &[&polygon]);
+
+        assert_eq!(
+            result,
+            r#"{"hierarchy":[{"admin_level":8,"name":"barfoo","relation_id":42}],"lat":1.0,"lon":2.0}"#
+        );
+    }
+}
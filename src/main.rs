@@ -1,65 +1,169 @@
 extern crate osmpbfreader;
 
+use crate::converter::{NameConfiguration, Polygon};
+use crate::extract::ExtractStrategy;
+use crate::lookup::QuerySource;
+use crate::osm_reader::{IdSelection, RelationFilter, RelationNodes};
+use crate::output::file_creator::FileCreator;
+use crate::output::file_writer_svg::SvgProjection;
+use crate::output::filename_template::{FilenameTemplate, DEFAULT_FILENAME_TEMPLATE};
+use crate::output::manifest::Manifest;
 use crate::output::output_handler::OutputHandlerConfiguration;
-use crate::output::OverwriteConfiguration;
-use clap::{crate_authors, crate_version, Arg,command};
+use crate::output::{FilenameConfiguration, OutputLayout, OverwriteConfiguration, TagSelection};
+use crate::region::Region;
+use crate::spatial_index::BoundaryIndex;
+use crate::tiles::{TileConfiguration, TileOutput};
+use clap::parser::ValueSource;
+use clap::{command, crate_authors, crate_version, Arg, ArgMatches, Command};
+use osmpbfreader::RelationId;
+use std::collections::HashSet;
+use std::fs::create_dir_all;
+use std::io::Write;
+use std::path::Path;
 
+mod block_index;
+mod cache;
+mod changes;
 mod converter;
+mod diff;
+mod extract;
+mod geometry;
+mod hierarchy;
+mod index_file;
+mod lookup;
+mod metrics;
+mod osc_reader;
 mod osm_reader;
 mod output;
+mod poly_reader;
+mod region;
+mod spatial_index;
+mod tiles;
 mod utils;
+mod validate_poly;
 
-fn main() {
-    const INPUT_ARG: &str = "INPUT";
-    const OUTPUT_FOLDER: &str = "OUTPUT";
-    const MIN_ADMIN_LEVEL_ARG: &str = "MIN_ADMIN_LEVEL";
-    const MAX_ADMIN_LEVEL_ARG: &str = "MAX_ADMIN_LEVEL";
-    const OVERWRITE_ARG: &str = "OVERWRITE";
-    const SKIP_ARG: &str = "SKIP";
-    const GEOJSON_ARG: &str = "GEOJSON";
+const INPUT_ARG: &str = "INPUT";
+const OUTPUT_FOLDER: &str = "OUTPUT";
+const MIN_ADMIN_LEVEL_ARG: &str = "MIN_ADMIN_LEVEL";
+const MAX_ADMIN_LEVEL_ARG: &str = "MAX_ADMIN_LEVEL";
+const OVERWRITE_ARG: &str = "OVERWRITE";
+const SKIP_ARG: &str = "SKIP";
+const GEOJSON_ARG: &str = "GEOJSON";
+const TAGS_ARG: &str = "TAGS";
+const BBOX_ARG: &str = "BBOX";
+const SPLIT_ANTIMERIDIAN_ARG: &str = "SPLIT_ANTIMERIDIAN";
+const SVG_ARG: &str = "SVG";
+const SVG_PROJECTION_ARG: &str = "SVG_PROJECTION";
+const SVG_INDEX_ARG: &str = "SVG_INDEX";
+const HIERARCHY_ARG: &str = "HIERARCHY";
+const POINTS_ARG: &str = "POINTS";
+const MANIFEST_ARG: &str = "MANIFEST";
+const FILENAME_TEMPLATE_ARG: &str = "FILENAME_TEMPLATE";
+const LAYOUT_ARG: &str = "LAYOUT";
+const PORTABLE_FILENAMES_ARG: &str = "PORTABLE_FILENAMES";
+const EXTRACT_ARG: &str = "EXTRACT";
+const MVT_ARG: &str = "MVT";
+const MIN_ZOOM_ARG: &str = "MIN_ZOOM";
+const MAX_ZOOM_ARG: &str = "MAX_ZOOM";
+const NAME_TAGS_ARG: &str = "NAME_TAGS";
+const NO_NAME_PREFIX_ARG: &str = "NO_NAME_PREFIX";
+const UNNAMED_TEMPLATE_ARG: &str = "UNNAMED_TEMPLATE";
+const POINT_ARG: &str = "POINT";
+const CSV_ARG: &str = "CSV";
+const MIN_AREA_ARG: &str = "MIN_AREA";
+const MAX_AREA_ARG: &str = "MAX_AREA";
+const RELATION_IDS_ARG: &str = "RELATION_IDS";
+const RELATION_IDS_FILE_ARG: &str = "RELATION_IDS_FILE";
+const NAME_REGEX_ARG: &str = "NAME_REGEX";
+const ISO3166_ARG: &str = "ISO3166";
+const WITHIN_BBOX_ARG: &str = "WITHIN_BBOX";
+const WITHIN_POLY_ARG: &str = "WITHIN_POLY";
+const FULLY_WITHIN_ARG: &str = "FULLY_WITHIN";
+
+const POLY_FILES_ARG: &str = "POLY_FILES";
+const PREVIOUS_ARG: &str = "PREVIOUS";
+const REPORT_ARG: &str = "REPORT";
+const CHANGES_ARG: &str = "CHANGES";
+const HAUSDORFF_THRESHOLD_ARG: &str = "HAUSDORFF_THRESHOLD";
+const AREA_THRESHOLD_ARG: &str = "AREA_THRESHOLD";
+const OLD_ARG: &str = "OLD";
+const CACHE_ARG: &str = "CACHE";
+const OSC_ARG: &str = "OSC";
+const INDEX_ARG: &str = "INDEX";
 
+const LOOKUP_COMMAND: &str = "lookup";
+const VALIDATE_POLY_COMMAND: &str = "validate-poly";
+const DIFF_COMMAND: &str = "diff";
+const BUILD_INDEX_COMMAND: &str = "build-index";
+
+fn main() {
     let matches = command!("OSM Extract Polygon")
         .version(crate_version!())
         .author(crate_authors!())
         .about(
             "Extracts administrative boundaries of OSM pbf files and produces polygon files compatible with Osmosis.",
         )
+        .subcommand_negates_reqs(true)
+        .args(input_args("8", "8"))
+        .mut_arg(INPUT_ARG, |arg| {
+            arg.visible_alias("new")
+                .help("input file; with --old the newer of the two files")
+                .required(false)
+                .required_unless_present(OSC_ARG)
+        })
         .arg(
-            Arg::new(INPUT_ARG)
-                .short('f')
-                .long("file")
+            Arg::new(OLD_ARG)
+                .long("old")
                 .value_name("filename")
-                .help("input file")
-                .required(true)
+                .help("older input file; only the outputs of the boundaries whose tags, members, or geometry changed since then are written, the others are kept")
+                .required(false)
                 .num_args(1),
         )
         .arg(
-            Arg::new(MIN_ADMIN_LEVEL_ARG)
-                .short('m')
-                .long("min")
-                .value_name("min_admin_level")
-                .help("minimum administrative level (can take value from 1-11) [default: 8]")
+            Arg::new(CACHE_ARG)
+                .long("cache")
+                .value_name("filename")
+                .help("file the relations, ways, and nodes of the boundaries are stored in after reading the input file; with --osc it is read instead of an input file and updated")
                 .required(false)
                 .num_args(1),
         )
         .arg(
-            Arg::new(MAX_ADMIN_LEVEL_ARG)
-                .short('x')
-                .long("max")
-                .value_name("max_admin_level")
-                .help("max administrative level (can take value from 1-11) [default: 8]")
+            Arg::new(OSC_ARG)
+                .long("osc")
+                .value_name("osc_file")
+                .help("OSM change files (.osc or .osc.gz) applied in the given order to --cache; only the outputs of the boundaries that changed are written, the others are kept. Use the same selection options as when the cache was created, a cache created with other options is refused")
                 .required(false)
-                .num_args(1),
+                .requires(CACHE_ARG)
+                .requires(OUTPUT_FOLDER)
+                .conflicts_with_all([INPUT_ARG, OLD_ARG, EXTRACT_ARG])
+                .num_args(1..),
         )
+        .mut_arg(INDEX_ARG, |arg| arg.conflicts_with_all([OLD_ARG, CACHE_ARG, OSC_ARG, EXTRACT_ARG]))
         .arg(
             Arg::new(OUTPUT_FOLDER)
                 .short('p')
                 .long("path")
                 .value_name("path")
-                .help("path to which the output will be saved to [default: '<input_filename>_polygons/']")
+                .help("path to which the output will be saved to [default: '<input_filename>_polygons/', with --index '<index_filename>_polygons/']")
                 .required(false)
                 .num_args(1),
         )
+        .arg(
+            Arg::new(MIN_AREA_ARG)
+                .long("min-area")
+                .value_name("km2")
+                .help("only extract boundaries with an area of at least the given value in km²")
+                .value_parser(clap::value_parser!(f64))
+                .num_args(1),
+        )
+        .arg(
+            Arg::new(MAX_AREA_ARG)
+                .long("max-area")
+                .value_name("km2")
+                .help("only extract boundaries with an area of at most the given value in km²")
+                .value_parser(clap::value_parser!(f64))
+                .num_args(1),
+        )
         .arg(
             Arg::new(OVERWRITE_ARG)
             .short('o')
@@ -81,17 +185,514 @@ fn main() {
             .num_args(0)
             .help("set this flag to generate geojson output")
         )
+        .arg(
+            Arg::new(TAGS_ARG)
+                .long("tags")
+                .value_name("tags")
+                .help("comma separated list of relation tags copied into the geojson properties, '*' matches any characters (e.g., 'wikidata,name:*'); 'all' copies all tags")
+                .value_delimiter(',')
+                .num_args(1),
+        )
+        .arg(
+            Arg::new(SPLIT_ANTIMERIDIAN_ARG)
+                .long("split-antimeridian")
+                .num_args(0)
+                .help("set this flag to split boundaries crossing the antimeridian into a MultiPolygon in the geojson output; the .poly files are not split"),
+        )
+        .arg(
+            Arg::new(BBOX_ARG)
+                .long("bbox")
+                .num_args(0)
+                .help("set this flag to additionally generate a .bbox file per boundary that can be passed to osmium's --bbox option"),
+        )
+        .arg(
+            Arg::new(SVG_ARG)
+            .long("svg")
+            .num_args(0)
+            .help("set this flag to generate an svg preview image per boundary")
+        )
+        .arg(
+            Arg::new(SVG_PROJECTION_ARG)
+                .long("svg-projection")
+                .value_name("projection")
+                .help("projection used for the svg previews [default: equirectangular]")
+                .value_parser(["equirectangular", "mercator"])
+                .required(false)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new(SVG_INDEX_ARG)
+            .long("svg-index")
+            .num_args(0)
+            .requires(SVG_ARG)
+            .help("set this flag to additionally generate an index.html showing all svg previews")
+        )
+        .arg(
+            Arg::new(HIERARCHY_ARG)
+                .long("hierarchy")
+                .num_args(0)
+                .help("set this flag to additionally generate a hierarchy.json containing the tree of the boundaries"),
+        )
+        .arg(
+            Arg::new(POINTS_ARG)
+                .long("points")
+                .num_args(0)
+                .help("set this flag to additionally generate a points.geojson containing the admin_centre and label nodes of the boundaries"),
+        )
+        .arg(
+            Arg::new(MANIFEST_ARG)
+                .long("manifest")
+                .num_args(0)
+                .help("set this flag to additionally generate a manifest.json and manifest.csv listing every boundary with its files, metrics, and problems"),
+        )
+        .arg(
+            Arg::new(FILENAME_TEMPLATE_ARG)
+                .long("filename-template")
+                .value_name("template")
+                .help("template for the output filenames without extension; supports {name}, {relation_id}, {admin_level}, and {tag:<key>}, '/' creates subdirectories [default: {name}]")
+                .required(false)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new(LAYOUT_ARG)
+                .long("layout")
+                .value_name("layout")
+                .help("'hierarchical' places every file in a folder named after its containing boundary; boundaries whose parent was not extracted are placed in '_no_parent/' [default: flat]")
+                .value_parser(["flat", "hierarchical"])
+                .required(false)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new(PORTABLE_FILENAMES_ARG)
+                .long("portable-filenames")
+                .num_args(0)
+                .help("set this flag to transliterate filenames to ASCII and avoid names that are invalid on Windows; the files still contain the original names"),
+        )
+        .arg(
+            Arg::new(EXTRACT_ARG)
+                .long("extract")
+                .value_name("strategy")
+                .help("additionally write a clipped .osm.pbf file per boundary; 'complete-ways' also includes nodes of ways crossing the boundary [default: complete-ways]")
+                .value_parser(["simple", "complete-ways"])
+                .required(false)
+                .num_args(0..=1)
+                .default_missing_value("complete-ways"),
+        )
+        .arg(
+            Arg::new(MVT_ARG)
+                .long("mvt")
+                .value_name("tiles_path")
+                .help("additionally generate Mapbox Vector Tiles; written as z/x/y directory tree, or as MBTiles if the path ends with '.mbtiles'")
+                .required(false)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new(MIN_ZOOM_ARG)
+                .long("min-zoom")
+                .value_name("min_zoom")
+                .help("minimum zoom level of the generated vector tiles [default: 0]")
+                .required(false)
+                .num_args(1),
+        )
+        .arg(
+            Arg::new(MAX_ZOOM_ARG)
+                .long("max-zoom")
+                .value_name("max_zoom")
+                .help("maximum zoom level of the generated vector tiles (can take value up to 20) [default: 10]")
+                .required(false)
+                .num_args(1),
+        )
+        .subcommand(
+            Command::new(LOOKUP_COMMAND)
+                .about("Looks up the administrative boundaries containing the given coordinates.")
+                .args(input_args("1", "11"))
+                .arg(
+                    Arg::new(POINT_ARG)
+                        .value_name("lat,lon")
+                        .help("coordinates to look up; if neither points nor --csv are given, they are read line by line from stdin")
+                        .allow_hyphen_values(true)
+                        .num_args(0..),
+                )
+                .arg(
+                    Arg::new(CSV_ARG)
+                        .long("csv")
+                        .value_name("csv_file")
+                        .help("csv file with one lat,lon pair per line")
+                        .required(false)
+                        .conflicts_with(POINT_ARG)
+                        .num_args(1),
+                ),
+        )
+        .subcommand(
+            Command::new(BUILD_INDEX_COMMAND)
+                .about("Reads the boundaries of the input file once and stores them with their rings and tags in an index file, which is read with --index instead of the input file.")
+                .arg(
+                    Arg::new(INPUT_ARG)
+                        .short('f')
+                        .long("file")
+                        .value_name("filename")
+                        .help("input file")
+                        .required(true)
+                        .num_args(1),
+                )
+                // only the admin levels of the other selection options are applied when the index is built
+                .args(admin_level_args("1", "11"))
+                .arg(
+                    Arg::new(INDEX_ARG)
+                        .short('i')
+                        .long("index")
+                        .value_name("filename")
+                        .help("file the index is written to")
+                        .required(true)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new(OVERWRITE_ARG)
+                        .short('o')
+                        .long("overwrite")
+                        .num_args(0)
+                        .help("set this flag to overwrite an existing index file without asking"),
+                ),
+        )
+        .subcommand(
+            Command::new(VALIDATE_POLY_COMMAND)
+                .about("Checks Osmosis .poly files for syntax errors, unclosed rings, and self-intersections.")
+                .arg(
+                    Arg::new(POLY_FILES_ARG)
+                        .value_name("poly_file")
+                        .help("the .poly files to check")
+                        .required(true)
+                        .num_args(1..),
+                )
+                .arg(
+                    Arg::new(GEOJSON_ARG)
+                        .short('g')
+                        .long("geojson")
+                        .num_args(0)
+                        .help("set this flag to convert every .poly file to a .geojson file next to it"),
+                )
+                .arg(
+                    Arg::new(OVERWRITE_ARG)
+                        .short('o')
+                        .long("overwrite")
+                        .num_args(0)
+                        .help("set this flag to overwrite existing .geojson files without asking"),
+                ),
+        )
+        .subcommand(
+            Command::new(DIFF_COMMAND)
+                .about("Compares the boundaries of the input file with a previous output folder.")
+                .args(input_args("8", "8"))
+                .arg(
+                    Arg::new(PREVIOUS_ARG)
+                        .long("previous")
+                        .value_name("folder")
+                        .help("output folder of a previous extraction that was run with --manifest")
+                        .required(true)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new(REPORT_ARG)
+                        .long("report")
+                        .value_name("filename")
+                        .help("file the json report is written to [default: diff.json]")
+                        .default_value("diff.json")
+                        .hide_default_value(true)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new(CHANGES_ARG)
+                        .long("changes")
+                        .value_name("filename")
+                        .help("additionally write a geojson file with the added, removed, and changed areas")
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new(HAUSDORFF_THRESHOLD_ARG)
+                        .long("hausdorff-threshold")
+                        .value_name("meters")
+                        .help("a boundary counts as changed if its Hausdorff distance to the previous version is bigger [default: 1]")
+                        .value_parser(clap::value_parser!(f64))
+                        .default_value("1")
+                        .hide_default_value(true)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new(AREA_THRESHOLD_ARG)
+                        .long("area-threshold")
+                        .value_name("km2")
+                        .help("a boundary counts as changed if the area of the symmetric difference to the previous version is bigger [default: 0]")
+                        .value_parser(clap::value_parser!(f64))
+                        .default_value("0")
+                        .hide_default_value(true)
+                        .num_args(1),
+                )
+                .arg(
+                    Arg::new(OVERWRITE_ARG)
+                        .short('o')
+                        .long("overwrite")
+                        .num_args(0)
+                        .help("set this flag to overwrite existing report files without asking"),
+                ),
+        )
         .get_matches();
 
+    match matches.subcommand() {
+        Some((LOOKUP_COMMAND, lookup_matches)) => lookup(lookup_matches),
+        Some((DIFF_COMMAND, diff_matches)) => diff(diff_matches),
+        Some((BUILD_INDEX_COMMAND, build_index_matches)) => build_index(build_index_matches),
+        Some((VALIDATE_POLY_COMMAND, validate_matches)) => validate_poly(validate_matches),
+        _ => extract_polygons(&matches),
+    }
+}
+
+fn input_args(default_min_admin_level: &'static str, default_max_admin_level: &'static str) -> Vec<Arg> {
+    let mut args = vec![Arg::new(INPUT_ARG)
+        .short('f')
+        .long("file")
+        .value_name("filename")
+        .help("input file")
+        .required_unless_present(INDEX_ARG)
+        .num_args(1)];
+    args.extend(admin_level_args(default_min_admin_level, default_max_admin_level));
+    args.extend(vec![
+        Arg::new(NAME_TAGS_ARG)
+            .long("name-tags")
+            .value_name("tags")
+            .help("comma separated list of tags used as name of a boundary, the first one present is used [default: name]")
+            .value_delimiter(',')
+            .num_args(1),
+        Arg::new(NO_NAME_PREFIX_ARG)
+            .long("no-name-prefix")
+            .num_args(0)
+            .help("set this flag to not prefix names with the value of the tag 'name:prefix'"),
+        Arg::new(UNNAMED_TEMPLATE_ARG)
+            .long("unnamed-template")
+            .value_name("template")
+            .help("name used for boundaries without any of the name tags; {id} and {admin_level} are replaced by the relation id and admin level [default: UNKNOWN_NAME]")
+            .num_args(1),
+        Arg::new(RELATION_IDS_ARG)
+            .long("relation-ids")
+            .value_name("ids")
+            .help("comma separated list of relation ids to read; replaces the admin level filter, unless --min or --max are given")
+            .num_args(1),
+        Arg::new(RELATION_IDS_FILE_ARG)
+            .long("relation-ids-file")
+            .value_name("filename")
+            .help("file with relation ids to read (separated by commas or whitespace, '#' starts a comment); combined with --relation-ids")
+            .num_args(1),
+        Arg::new(NAME_REGEX_ARG)
+            .long("name-regex")
+            .value_name("regex")
+            .help("only read boundaries with a name (or a name:* variant, or the name with its prefix) matching the regular expression; case-insensitive")
+            .num_args(1),
+        Arg::new(ISO3166_ARG)
+            .long("iso3166")
+            .value_name("codes")
+            .help("comma separated list of ISO 3166-1 or ISO 3166-2 codes (e.g., 'DE-BW,DE-BY'); only boundaries tagged with one of them are read")
+            .num_args(1),
+        Arg::new(WITHIN_BBOX_ARG)
+            .long("within-bbox")
+            .value_name("minlon,minlat,maxlon,maxlat")
+            .help("only read boundaries intersecting the given bounding box")
+            .conflicts_with(WITHIN_POLY_ARG)
+            .num_args(1),
+        Arg::new(WITHIN_POLY_ARG)
+            .long("within-poly")
+            .value_name("filename")
+            .help("only read boundaries intersecting the polygon of the given Osmosis .poly file")
+            .num_args(1),
+        Arg::new(FULLY_WITHIN_ARG)
+            .long("fully-within")
+            .num_args(0)
+            .help("set this flag to only read boundaries lying completely inside of --within-bbox or --within-poly"),
+        Arg::new(INDEX_ARG)
+            .long("index")
+            .value_name("filename")
+            .help("read the boundaries from an index file written by build-index instead of an input file; the selection options are applied to the boundaries in the index")
+            .conflicts_with(INPUT_ARG)
+            .num_args(1),
+    ]);
+    args
+}
+
+fn admin_level_args(default_min_admin_level: &'static str, default_max_admin_level: &'static str) -> [Arg; 2] {
+    [
+        Arg::new(MIN_ADMIN_LEVEL_ARG)
+            .short('m')
+            .long("min")
+            .value_name("min_admin_level")
+            .help(format!(
+                "minimum administrative level (can take value from 1-11) [default: {}]",
+                default_min_admin_level
+            ))
+            .default_value(default_min_admin_level)
+            .hide_default_value(true)
+            .num_args(1),
+        Arg::new(MAX_ADMIN_LEVEL_ARG)
+            .short('x')
+            .long("max")
+            .value_name("max_admin_level")
+            .help(format!(
+                "max administrative level (can take value from 1-11) [default: {}]",
+                default_max_admin_level
+            ))
+            .default_value(default_max_admin_level)
+            .hide_default_value(true)
+            .num_args(1),
+    ]
+}
+
+fn read_polygons(matches: &ArgMatches, cache_filename: Option<&String>) -> Vec<Polygon> {
+    let (filter, name_config) = read_configuration(matches);
+
+    let relations = match matches.get_one::<String>(INDEX_ARG) {
+        Some(index_filename) => {
+            println!("Using index file: {}", index_filename);
+            index_file::read(index_filename, &filter).unwrap_or_else(|e| {
+                println!("error: could not read the index: {}", e);
+                std::process::exit(-1);
+            })
+        }
+        None => {
+            let in_filename = matches.get_one::<String>(INPUT_ARG).unwrap();
+            println!("Using input file: {}", in_filename);
+            read_relations(in_filename, &filter, cache_filename)
+        }
+    };
+    to_polygons(relations, &filter, &name_config)
+}
+
+fn read_changed_polygons(
+    matches: &ArgMatches,
+    old_filename: &str,
+    cache_filename: Option<&String>,
+) -> (Vec<Polygon>, Vec<changes::RelationChange>) {
+    let (filter, name_config) = read_configuration(matches);
+
+    let in_filename = matches.get_one::<String>(INPUT_ARG).unwrap();
+    println!("Using old input file: {}", old_filename);
+    let old_relations = osm_reader::read_osm(old_filename, &filter).unwrap_or_else(|e| {
+        println!("error: could not read the old input file: {}", e);
+        std::process::exit(-1);
+    });
+    println!("Using new input file: {}", in_filename);
+    let new_relations = read_relations(in_filename, &filter, cache_filename);
+
+    let changes = changes::detect(&old_relations, &new_relations);
+    (to_polygons(new_relations, &filter, &name_config), changes)
+}
+
+fn read_updated_polygons(
+    matches: &ArgMatches,
+    cache_filename: &str,
+    osc_filenames: &[String],
+) -> (Vec<Polygon>, Vec<changes::RelationChange>) {
+    let (filter, name_config) = read_configuration(matches);
+
+    println!("Using cache file: {}", cache_filename);
+    let mut objects = cache::read(cache_filename, &filter.fingerprint()).unwrap_or_else(|e| {
+        println!("error: could not read the cache: {}", e);
+        std::process::exit(-1);
+    });
+    let (old_objects, previously_missing) = osm_reader::select(&objects, &filter);
+    let old_relations = osm_reader::assemble(&old_objects, &filter);
+
+    for osc_filename in osc_filenames {
+        println!("Applying change file: {}", osc_filename);
+        match osc_reader::read_osc(osc_filename) {
+            Ok(osm_changes) => osc_reader::apply(&mut objects, osm_changes),
+            Err(e) => {
+                println!("error: {}", e);
+                std::process::exit(-1);
+            }
+        }
+    }
+
+    /*
+        the change files only contain the objects that changed; a boundary that now references
+        an unchanged way or node it did not reference before cannot be completed from the cache
+    */
+    let (new_objects, missing) = osm_reader::select(&objects, &filter);
+    let newly_missing = missing.difference(&previously_missing).count();
+    if newly_missing > 0 {
+        println!(
+            "error: {} objects referenced by the boundaries are neither in the cache nor in the change files, the cache is left unchanged; create it again from a full input file",
+            newly_missing
+        );
+        std::process::exit(-1);
+    }
+    write_cache(cache_filename, &new_objects, &filter);
+
+    let new_relations = osm_reader::assemble(&new_objects, &filter);
+    let changes = changes::detect(&old_relations, &new_relations);
+    (to_polygons(new_relations, &filter, &name_config), changes)
+}
+
+fn read_relations(in_filename: &str, filter: &RelationFilter, cache_filename: Option<&String>) -> Vec<RelationNodes> {
+    let objects = osm_reader::read_osm_objects(in_filename, filter).unwrap();
+    if let Some(cache_filename) = cache_filename {
+        write_cache(cache_filename, &objects, filter);
+    }
+    osm_reader::assemble(&objects, filter)
+}
+
+fn write_cache(cache_filename: &str, objects: &osm_reader::OsmObjects, filter: &RelationFilter) {
+    match cache::write(cache_filename, objects, &filter.fingerprint()) {
+        Ok(_) => println!("{}: successfully written", cache_filename),
+        Err(e) => println!("{}: {}", cache_filename, e),
+    }
+}
+
+fn to_polygons(
+    relations: Vec<RelationNodes>,
+    filter: &RelationFilter,
+    name_config: &NameConfiguration,
+) -> Vec<Polygon> {
+    let mut polygons = converter::convert(relations, name_config);
+
+    if let Some(region) = &filter.region {
+        let count = polygons.len();
+        polygons.retain(|polygon| region.selects(polygon));
+        println!("kept {} of {} boundaries in the selected region", polygons.len(), count);
+    }
+
+    polygons
+}
+
+fn write_changes(path: &str, changes: &[changes::RelationChange], overwrite_configuration: OverwriteConfiguration) {
+    println!("{} boundaries changed:", changes.len());
+    for change in changes {
+        println!("  {}", changes::describe(change));
+    }
+
+    let removed = changes.iter().filter(|c| c.is_removed()).count();
+    if removed > 0 {
+        println!("the files of the {} removed boundaries are not deleted", removed);
+    }
+
+    let filename = Path::new(path).join("changes.json");
+    let mut file_creator = FileCreator {
+        overwrite_mode_config: overwrite_configuration,
+    };
+    let result = create_dir_all(path)
+        .and_then(|_| file_creator.create_file(&filename))
+        .and_then(|mut file| file.write_all(changes::render(changes).to_string().as_bytes()));
+    match result {
+        Ok(_) => println!("{}: successfully written", filename.display()),
+        Err(e) => println!("{}: {}", filename.display(), e),
+    }
+}
+
+fn read_admin_levels(matches: &ArgMatches) -> (i8, i8) {
     let min_admin_level = matches
         .get_one::<String>(MIN_ADMIN_LEVEL_ARG)
-        .unwrap_or(&("8".to_string()))
+        .unwrap()
         .parse::<i8>()
         .unwrap();
-
     let max_admin_level = matches
         .get_one::<String>(MAX_ADMIN_LEVEL_ARG)
-        .unwrap_or(&("8".to_string()))
+        .unwrap()
         .parse::<i8>()
         .unwrap();
 
@@ -103,6 +704,206 @@ fn main() {
         std::process::exit(-1);
     }
 
+    (min_admin_level, max_admin_level)
+}
+
+fn read_configuration(matches: &ArgMatches) -> (RelationFilter, NameConfiguration) {
+    let (min_admin_level, max_admin_level) = read_admin_levels(matches);
+
+    let default_name_config = NameConfiguration::default();
+    let name_config = NameConfiguration {
+        name_tags: matches
+            .get_many::<String>(NAME_TAGS_ARG)
+            .map(|tags| tags.map(|t| t.trim().to_string()).collect())
+            .unwrap_or(default_name_config.name_tags),
+        use_prefix: !matches.get_flag(NO_NAME_PREFIX_ARG),
+        fallback_template: matches
+            .get_one::<String>(UNNAMED_TEMPLATE_ARG)
+            .cloned()
+            .unwrap_or(default_name_config.fallback_template),
+    };
+
+    let filter = RelationFilter {
+        min_admin: min_admin_level,
+        max_admin: max_admin_level,
+        relation_ids: read_relation_ids(matches),
+        name_regex: matches.get_one::<String>(NAME_REGEX_ARG).map(|pattern| {
+            match osm_reader::compile_name_regex(pattern) {
+                Ok(regex) => regex,
+                Err(e) => {
+                    println!("error: invalid --name-regex: {}", e);
+                    std::process::exit(-1);
+                }
+            }
+        }),
+        iso3166: matches
+            .get_one::<String>(ISO3166_ARG)
+            .map(|codes| osm_reader::parse_iso3166_codes(codes)),
+        region: read_region(matches),
+    };
+
+    (filter, name_config)
+}
+
+fn read_region(matches: &ArgMatches) -> Option<Region> {
+    let fully_within = matches.get_flag(FULLY_WITHIN_ARG);
+
+    let region = if let Some(bbox) = matches.get_one::<String>(WITHIN_BBOX_ARG) {
+        Region::from_bbox(bbox, fully_within).map_err(|e| format!("invalid --within-bbox: {}", e))
+    } else if let Some(filename) = matches.get_one::<String>(WITHIN_POLY_ARG) {
+        poly_reader::read_poly(filename)
+            .and_then(|poly| Region::from_geometry(poly.to_multi_polygon(), fully_within))
+            .map_err(|e| format!("invalid --within-poly: {}", e))
+    } else if fully_within {
+        Err(String::from("--fully-within requires --within-bbox or --within-poly"))
+    } else {
+        return None;
+    };
+
+    match region {
+        Ok(region) => Some(region),
+        Err(e) => {
+            println!("error: {}", e);
+            std::process::exit(-1);
+        }
+    }
+}
+
+fn read_relation_ids(matches: &ArgMatches) -> Option<(HashSet<RelationId>, IdSelection)> {
+    let ids_arg = matches.get_one::<String>(RELATION_IDS_ARG);
+    let ids_file_arg = matches.get_one::<String>(RELATION_IDS_FILE_ARG);
+    if ids_arg.is_none() && ids_file_arg.is_none() {
+        return None;
+    }
+
+    let mut ids = HashSet::new();
+    let parsed = ids_arg
+        .map(|text| osm_reader::parse_relation_ids(text).map_err(|e| format!("--relation-ids: {}", e)))
+        .into_iter()
+        .chain(ids_file_arg.map(|filename| osm_reader::read_relation_ids_file(filename)));
+    for result in parsed {
+        match result {
+            Ok(parsed_ids) => ids.extend(parsed_ids),
+            Err(e) => {
+                println!("error: {}", e);
+                std::process::exit(-1);
+            }
+        }
+    }
+
+    // explicitly given admin levels are still respected, the defaults are not
+    let is_explicit = |arg| matches.value_source(arg) == Some(ValueSource::CommandLine);
+    let selection = if is_explicit(MIN_ADMIN_LEVEL_ARG) || is_explicit(MAX_ADMIN_LEVEL_ARG) {
+        IdSelection::Intersect
+    } else {
+        IdSelection::Replace
+    };
+
+    println!("selecting {} relations by id", ids.len());
+    Some((ids, selection))
+}
+
+fn filter_by_area(polygons: &mut Vec<Polygon>, min_area: Option<f64>, max_area: Option<f64>) {
+    if min_area.is_none() && max_area.is_none() {
+        return;
+    }
+
+    let count = polygons.len();
+    polygons.retain(|polygon| {
+        let area = metrics::area_km2(polygon);
+        min_area.is_none_or(|min| min <= area) && max_area.is_none_or(|max| area <= max)
+    });
+    println!("{} of {} boundaries are within the area limits", polygons.len(), count);
+}
+
+fn lookup(matches: &ArgMatches) {
+    let source = if let Some(csv_filename) = matches.get_one::<String>(CSV_ARG) {
+        QuerySource::Csv(csv_filename.clone())
+    } else if let Some(points) = matches.get_many::<String>(POINT_ARG) {
+        QuerySource::Arguments(points.cloned().collect())
+    } else {
+        QuerySource::Stdin
+    };
+
+    let polygons = read_polygons(matches, None);
+    let index = BoundaryIndex::new(&polygons);
+
+    if let Err(e) = lookup::run(&index, source) {
+        println!("error! {:?}", e);
+    }
+}
+
+fn diff(matches: &ArgMatches) {
+    let polygons = read_polygons(matches, None);
+
+    let thresholds = diff::Thresholds {
+        hausdorff_m: *matches.get_one::<f64>(HAUSDORFF_THRESHOLD_ARG).unwrap(),
+        area_km2: *matches.get_one::<f64>(AREA_THRESHOLD_ARG).unwrap(),
+    };
+    let mut file_creator = overwrite_file_creator(matches);
+
+    let result = diff::run(
+        &polygons,
+        matches.get_one::<String>(PREVIOUS_ARG).unwrap(),
+        &thresholds,
+        Path::new(matches.get_one::<String>(REPORT_ARG).unwrap()),
+        matches.get_one::<String>(CHANGES_ARG).map(Path::new),
+        &mut file_creator,
+    );
+
+    if let Err(e) = result {
+        println!("error: {}", e);
+        std::process::exit(-1);
+    }
+}
+
+fn build_index(matches: &ArgMatches) {
+    let (min_admin_level, max_admin_level) = read_admin_levels(matches);
+    let filter = RelationFilter {
+        min_admin: min_admin_level,
+        max_admin: max_admin_level,
+        relation_ids: None,
+        name_regex: None,
+        iso3166: None,
+        region: None,
+    };
+
+    let in_filename = matches.get_one::<String>(INPUT_ARG).unwrap();
+    let index_filename = matches.get_one::<String>(INDEX_ARG).unwrap();
+    println!("Using input file: {}", in_filename);
+    let relations = osm_reader::read_osm(in_filename, &filter).unwrap();
+
+    let mut file_creator = overwrite_file_creator(matches);
+    match index_file::write(index_filename, in_filename, &relations, &mut file_creator) {
+        Ok(size) => println!("success! wrote {} boundaries to {}!", size, index_filename),
+        Err(e) => {
+            println!("error! {:?}", e);
+            std::process::exit(-1);
+        }
+    }
+}
+
+fn validate_poly(matches: &ArgMatches) {
+    let filenames: Vec<String> = matches.get_many::<String>(POLY_FILES_ARG).unwrap().cloned().collect();
+    let mut file_creator = overwrite_file_creator(matches);
+
+    if !validate_poly::run(&filenames, matches.get_flag(GEOJSON_ARG), &mut file_creator) {
+        std::process::exit(1);
+    }
+}
+
+// the subcommands only have --overwrite, without it the user is asked before a file is replaced
+fn overwrite_file_creator(matches: &ArgMatches) -> FileCreator {
+    FileCreator {
+        overwrite_mode_config: if matches.get_flag(OVERWRITE_ARG) {
+            OverwriteConfiguration::OverwriteAll
+        } else {
+            OverwriteConfiguration::Ask
+        },
+    }
+}
+
+fn extract_polygons(matches: &ArgMatches) {
     let overwrite_all = matches.get_flag(OVERWRITE_ARG);
     let skip_all = matches.get_flag(SKIP_ARG);
 
@@ -121,23 +922,178 @@ fn main() {
 
     let geojson_output = matches.get_flag(GEOJSON_ARG);
 
-    let output_handler_config = OutputHandlerConfiguration {
+    let geojson_tags = match matches.get_many::<String>(TAGS_ARG) {
+        None => TagSelection::Nothing,
+        Some(tags) => {
+            let patterns: Vec<String> = tags.map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect();
+            if patterns.iter().any(|t| t == "all") {
+                TagSelection::All
+            } else {
+                TagSelection::Patterns(patterns)
+            }
+        }
+    };
+
+    let svg_output = if matches.get_flag(SVG_ARG) {
+        match matches.get_one::<String>(SVG_PROJECTION_ARG).map(|s| s.as_str()) {
+            Some("mercator") => Some(SvgProjection::WebMercator),
+            _ => Some(SvgProjection::Equirectangular),
+        }
+    } else {
+        None
+    };
+    let svg_index = matches.get_flag(SVG_INDEX_ARG);
+    let hierarchy_output = matches.get_flag(HIERARCHY_ARG);
+    let manifest_output = matches.get_flag(MANIFEST_ARG);
+
+    let filename_template = matches
+        .get_one::<String>(FILENAME_TEMPLATE_ARG)
+        .map(|s| s.as_str())
+        .unwrap_or(DEFAULT_FILENAME_TEMPLATE);
+    let filename_template = match FilenameTemplate::parse(filename_template) {
+        Ok(template) => template,
+        Err(e) => {
+            println!("error: invalid --filename-template: {}", e);
+            std::process::exit(-1);
+        }
+    };
+
+    let layout = match matches.get_one::<String>(LAYOUT_ARG).map(|s| s.as_str()) {
+        Some("hierarchical") => OutputLayout::Hierarchical,
+        _ => OutputLayout::Flat,
+    };
+
+    let filename_configuration = FilenameConfiguration {
+        template: filename_template,
+        layout,
+        portable: matches.get_flag(PORTABLE_FILENAMES_ARG),
+    };
+
+    let extract_strategy = match matches.get_one::<String>(EXTRACT_ARG).map(|s| s.as_str()) {
+        Some("simple") => Some(ExtractStrategy::Simple),
+        Some(_) => Some(ExtractStrategy::CompleteWays),
+        None => None,
+    };
+
+    let min_zoom = matches
+        .get_one::<String>(MIN_ZOOM_ARG)
+        .unwrap_or(&("0".to_string()))
+        .parse::<u8>()
+        .unwrap();
+
+    let max_zoom = matches
+        .get_one::<String>(MAX_ZOOM_ARG)
+        .unwrap_or(&("10".to_string()))
+        .parse::<u8>()
+        .unwrap();
+
+    if min_zoom > max_zoom || max_zoom > 20 {
+        println!(
+            "error: invalid zoom range --min-zoom={} --max-zoom={}",
+            min_zoom, max_zoom
+        );
+        std::process::exit(-1);
+    }
+
+    let tile_configuration = matches.get_one::<String>(MVT_ARG).map(|tiles_path| TileConfiguration {
+        output: TileOutput::from_path(tiles_path),
+        min_zoom,
+        max_zoom,
+    });
+
+    let mut output_handler_config = OutputHandlerConfiguration {
         overwrite_configuration,
         geojson_output,
+        geojson_tags,
+        split_antimeridian: matches.get_flag(SPLIT_ANTIMERIDIAN_ARG),
+        bbox_output: matches.get_flag(BBOX_ARG),
+        svg_output,
+        svg_index,
+        hierarchy_output,
+        points_output: matches.get_flag(POINTS_ARG),
+        filename_configuration: filename_configuration.clone(),
+        only_relation_ids: None,
     };
 
-    let in_filename = matches.get_one::<String>(INPUT_ARG).unwrap();
-    println!("Using input file: {}", in_filename);
+    // with --index the output is placed next to the index, without any input file (--osc) the output path is required
+    let in_filename = matches
+        .get_one::<String>(INPUT_ARG)
+        .or_else(|| matches.get_one::<String>(INDEX_ARG))
+        .map(|s| s.as_str())
+        .unwrap_or_default();
     let default_path = format!("{}_polygons/", in_filename);
     let path = matches.get_one::<String>(OUTPUT_FOLDER).unwrap_or(&default_path);
     println!("Output path: {}", path);
 
-    let relations = osm_reader::read_osm(in_filename, &min_admin_level, &max_admin_level);
-    let polygons = converter::convert(relations.unwrap());
-    let result = output::output_handler::write(path, &polygons, output_handler_config);
+    let cache_filename = matches.get_one::<String>(CACHE_ARG);
+    let osc_filenames: Option<Vec<String>> = matches.get_many::<String>(OSC_ARG).map(|f| f.cloned().collect());
+    let changed = match (matches.get_one::<String>(OLD_ARG), osc_filenames, cache_filename) {
+        (Some(old_filename), _, _) => Some(read_changed_polygons(matches, old_filename, cache_filename)),
+        (None, Some(osc_filenames), Some(cache_filename)) => {
+            Some(read_updated_polygons(matches, cache_filename, &osc_filenames))
+        }
+        _ => None,
+    };
+    let mut polygons = match changed {
+        None => read_polygons(matches, cache_filename),
+        Some((polygons, changes)) => {
+            write_changes(path, &changes, overwrite_configuration);
+            output_handler_config.only_relation_ids = Some(changes.iter().map(|c| c.relation_id).collect());
+            polygons
+        }
+    };
+    filter_by_area(
+        &mut polygons,
+        matches.get_one::<f64>(MIN_AREA_ARG).copied(),
+        matches.get_one::<f64>(MAX_AREA_ARG).copied(),
+    );
+    // the containing boundaries are only needed by the hierarchical layout, the hierarchy tree, and the GeoJSON properties
+    if geojson_output || hierarchy_output || matches!(filename_configuration.layout, OutputLayout::Hierarchical) {
+        hierarchy::assign_ancestors(&mut polygons);
+    }
+    let mut manifest = Manifest::new(path);
+    let only_relation_ids = output_handler_config.only_relation_ids.clone();
+    let result = output::output_handler::write(path, &polygons, output_handler_config, &mut manifest);
 
     match result {
         Ok(size) => println!("success! wrote {} files!", size),
         Err(e) => println!("error! {:?}", e),
     }
+
+    if let Some(strategy) = extract_strategy {
+        let mut file_creator = FileCreator {
+            overwrite_mode_config: overwrite_configuration,
+        };
+        let (kept, targets): (Vec<_>, Vec<_>) =
+            output::output_handler::paths_with_extension(path, &polygons, &filename_configuration, "osm.pbf")
+                .into_iter()
+                .partition(|(target, polygon)| output::output_handler::is_kept(&only_relation_ids, target, polygon));
+        for (target, polygon) in kept {
+            manifest.record(polygon.relation_id, &target, &Ok(()));
+        }
+        match extract::clipper::extract(in_filename, targets, strategy, &mut file_creator, &mut manifest) {
+            Ok(size) => println!("success! wrote {} extracts!", size),
+            Err(e) => println!("error! {:?}", e),
+        }
+    }
+
+    if let Some(tile_configuration) = tile_configuration {
+        let mut file_creator = FileCreator {
+            overwrite_mode_config: overwrite_configuration,
+        };
+        match tiles::tile_store::write_tiles(&polygons, &tile_configuration, &mut file_creator) {
+            Ok(size) => println!("success! wrote {} tiles!", size),
+            Err(e) => println!("error! {:?}", e),
+        }
+    }
+
+    if manifest_output {
+        let mut file_creator = FileCreator {
+            overwrite_mode_config: overwrite_configuration,
+        };
+        match manifest.write(&polygons, &mut file_creator) {
+            Ok(size) => println!("success! wrote {} manifest files!", size),
+            Err(e) => println!("error! {:?}", e),
+        }
+    }
 }
@@ -0,0 +1,126 @@
+use crate::converter::Polygon;
+use crate::geometry::{normalize_lon, to_continuous_multi_polygon};
+
+use geo::{Area, BoundingRect, Centroid, GeodesicArea};
+use geo_types::{MultiPolygon, Point as GeoPoint};
+use polylabel::polylabel;
+
+// relative to the size of the largest part of a boundary
+const POLE_OF_INACCESSIBILITY_PRECISION: f64 = 1.0 / 1000.0;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metrics {
+    pub area_km2: f64,
+    pub perimeter_km: f64,
+    pub centroid: Option<(f64, f64)>,
+    pub pole_of_inaccessibility: Option<(f64, f64)>,
+}
+
+impl Metrics {
+    /*
+        area and perimeter are computed on the ellipsoid and include the holes of the boundary;
+        the pole of inaccessibility is the point of the largest part that is farthest from its
+        border and hence a good position for a label, other than the centroid it is always inside
+    */
+    pub fn of_polygon(polygon: &Polygon) -> Metrics {
+        let multi_polygon = to_continuous_multi_polygon(polygon);
+        let (perimeter, area) = multi_polygon.geodesic_perimeter_area_unsigned();
+
+        Metrics {
+            area_km2: area / 1_000_000.0,
+            perimeter_km: perimeter / 1_000.0,
+            centroid: multi_polygon.centroid().map(to_lon_lat),
+            pole_of_inaccessibility: pole_of_inaccessibility(&multi_polygon),
+        }
+    }
+}
+
+pub fn area_km2(polygon: &Polygon) -> f64 {
+    to_continuous_multi_polygon(polygon).geodesic_area_unsigned() / 1_000_000.0
+}
+
+fn pole_of_inaccessibility(multi_polygon: &MultiPolygon<f64>) -> Option<(f64, f64)> {
+    let largest = multi_polygon
+        .0
+        .iter()
+        .max_by(|a, b| a.unsigned_area().total_cmp(&b.unsigned_area()))?;
+    let rect = largest.bounding_rect()?;
+    let tolerance = rect.width().max(rect.height()) * POLE_OF_INACCESSIBILITY_PRECISION;
+
+    if tolerance <= 0.0 {
+        return None;
+    }
+
+    polylabel(largest, &tolerance).ok().map(to_lon_lat)
+}
+
+fn to_lon_lat(point: GeoPoint<f64>) -> (f64, f64) {
+    (normalize_lon(point.x()), point.y())
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::Point;
+
+    fn ring(s: f32, n: f32, w: f32, e: f32) -> Vec<Point> {
+        vec![
+            Point { lat: s, lon: w },
+            Point { lat: s, lon: e },
+            Point { lat: n, lon: e },
+            Point { lat: n, lon: w },
+            Point { lat: s, lon: w },
+        ]
+    }
+
+    fn create_polygon(rings: Vec<Vec<Point>>) -> Polygon {
+        Polygon {
+            points: rings,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_metrics_of_square_at_equator() {
+        let result = Metrics::of_polygon(&create_polygon(vec![ring(0.0, 1.0, 0.0, 1.0)]));
+
+        assert!((result.area_km2 - 12308.0).abs() < 10.0);
+        assert!((result.perimeter_km - 4.0 * 111.0).abs() < 2.0);
+        let (lon, lat) = result.centroid.unwrap();
+        assert!((lon - 0.5).abs() < 1e-6 && (lat - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_metrics_account_for_holes() {
+        let result = Metrics::of_polygon(&create_polygon(vec![
+            ring(0.0, 3.0, 0.0, 3.0),
+            ring(1.0, 2.0, 0.5, 2.5),
+        ]));
+
+        assert!((result.area_km2 - 7.0 * 12308.0).abs() < 200.0);
+        let (lon, lat) = result.pole_of_inaccessibility.unwrap();
+        assert!(!(0.5..=2.5).contains(&lon) || !(1.0..=2.0).contains(&lat));
+    }
+
+    #[test]
+    fn test_metrics_at_antimeridian() {
+        let result = Metrics::of_polygon(&create_polygon(vec![ring(-1.0, 1.0, 179.0, -179.0)]));
+
+        assert!((result.area_km2 - 4.0 * 12308.0).abs() < 50.0);
+        let (lon, _) = result.centroid.unwrap();
+        assert!((lon.abs() - 180.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_metrics_of_empty_polygon() {
+        let result = Metrics::of_polygon(&create_polygon(vec![]));
+
+        assert_eq!(result, Metrics::default());
+    }
+}
@@ -0,0 +1,322 @@
+use crate::osm_reader::OsmObjects;
+
+use flate2::read::MultiGzDecoder;
+use osmpbfreader::{Node, NodeId, OsmId, OsmObj, Ref, Relation, RelationId, Tags, Way, WayId};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Create,
+    Modify,
+    Delete,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    pub action: Action,
+    pub object: OsmObj,
+}
+
+pub fn read_osc(filename: &str) -> Result<Vec<Change>, String> {
+    let file = File::open(filename).map_err(|e| format!("{}: {}", filename, e))?;
+    let result = if filename.ends_with(".gz") {
+        parse_osc(BufReader::new(MultiGzDecoder::new(file)))
+    } else {
+        parse_osc(BufReader::new(file))
+    };
+    result.map_err(|e| format!("{}: {}", filename, e))
+}
+
+pub fn parse_osc<R: BufRead>(input: R) -> Result<Vec<Change>, String> {
+    /*
+        osmChange format: <create>, <modify>, and <delete> blocks containing nodes, ways, and relations
+        in the OSM XML format; the tags, way nodes, and members are the child elements of the objects
+    */
+    let mut reader = Reader::from_reader(input);
+    let mut buffer = Vec::new();
+    let mut changes = Vec::new();
+    let mut action = None;
+    let mut current: Option<OsmObj> = None;
+
+    loop {
+        let position = reader.buffer_position();
+        let error = |message: String| format!("byte {}: {}", position, message);
+
+        let event = reader.read_event_into(&mut buffer).map_err(|e| error(e.to_string()))?;
+        let (element, is_empty) = match &event {
+            Event::Start(element) => (element, false),
+            Event::Empty(element) => (element, true),
+            Event::End(element) => {
+                match element.name().as_ref() {
+                    b"create" | b"modify" | b"delete" => action = None,
+                    b"node" | b"way" | b"relation" => {
+                        if let (Some(action), Some(object)) = (action, current.take()) {
+                            changes.push(Change { action, object });
+                        }
+                    }
+                    _ => {}
+                }
+                buffer.clear();
+                continue;
+            }
+            Event::Eof => break,
+            _ => {
+                buffer.clear();
+                continue;
+            }
+        };
+
+        match element.name().as_ref() {
+            b"create" => action = Some(Action::Create),
+            b"modify" => action = Some(Action::Modify),
+            b"delete" => action = Some(Action::Delete),
+            b"node" | b"way" | b"relation" => {
+                let object = parse_object(element).map_err(error)?;
+                match action {
+                    None => return Err(error(String::from("object outside of create, modify, or delete"))),
+                    Some(action) if is_empty => changes.push(Change { action, object }),
+                    Some(_) => current = Some(object),
+                }
+            }
+            b"tag" => {
+                let (key, value) = (
+                    attribute(element, "k").map_err(error)?,
+                    attribute(element, "v").map_err(error)?,
+                );
+                if let Some(object) = current.as_mut() {
+                    tags_of(object).insert(key.into(), value.into());
+                }
+            }
+            b"nd" => {
+                let node_id = NodeId(parse_id(element).map_err(error)?);
+                if let Some(OsmObj::Way(way)) = current.as_mut() {
+                    way.nodes.push(node_id);
+                }
+            }
+            b"member" => {
+                let member = parse_member(element).map_err(error)?;
+                if let Some(OsmObj::Relation(relation)) = current.as_mut() {
+                    relation.refs.push(member);
+                }
+            }
+            _ => {}
+        }
+        buffer.clear();
+    }
+
+    Ok(changes)
+}
+
+// changes are applied in the given order, later versions of an object replace earlier ones
+pub fn apply(objects: &mut OsmObjects, changes: Vec<Change>) {
+    for change in changes {
+        match (change.action, change.object) {
+            (Action::Delete, OsmObj::Node(node)) => {
+                objects.nodes.remove(&node.id);
+            }
+            (Action::Delete, OsmObj::Way(way)) => {
+                objects.ways.remove(&way.id);
+            }
+            (Action::Delete, OsmObj::Relation(relation)) => {
+                objects.relations.remove(&relation.id);
+            }
+            (_, OsmObj::Node(node)) => {
+                objects.nodes.insert(node.id, node);
+            }
+            (_, OsmObj::Way(way)) => {
+                objects.ways.insert(way.id, way.nodes);
+            }
+            (_, OsmObj::Relation(relation)) => {
+                objects.relations.insert(relation.id, relation);
+            }
+        }
+    }
+}
+
+fn tags_of(object: &mut OsmObj) -> &mut Tags {
+    match object {
+        OsmObj::Node(node) => &mut node.tags,
+        OsmObj::Way(way) => &mut way.tags,
+        OsmObj::Relation(relation) => &mut relation.tags,
+    }
+}
+
+fn parse_object(element: &BytesStart) -> Result<OsmObj, String> {
+    let id = parse_id(element)?;
+
+    Ok(match element.name().as_ref() {
+        b"node" => {
+            // deleted nodes do not need to have coordinates
+            let coordinate = |name: &str| match attribute(element, name) {
+                Ok(value) => parse_coordinate(&value),
+                Err(_) => Ok(0),
+            };
+            OsmObj::Node(Node {
+                id: NodeId(id),
+                tags: Tags::new(),
+                decimicro_lat: coordinate("lat")?,
+                decimicro_lon: coordinate("lon")?,
+            })
+        }
+        b"way" => OsmObj::Way(Way {
+            id: WayId(id),
+            tags: Tags::new(),
+            nodes: Vec::new(),
+        }),
+        _ => OsmObj::Relation(Relation {
+            id: RelationId(id),
+            tags: Tags::new(),
+            refs: Vec::new(),
+        }),
+    })
+}
+
+fn parse_member(element: &BytesStart) -> Result<Ref, String> {
+    let id = parse_id_attribute(element, "ref")?;
+    let member = match attribute(element, "type")?.as_str() {
+        "node" => OsmId::Node(NodeId(id)),
+        "way" => OsmId::Way(WayId(id)),
+        "relation" => OsmId::Relation(RelationId(id)),
+        other => return Err(format!("unknown member type '{}'", other)),
+    };
+
+    Ok(Ref {
+        member,
+        role: attribute(element, "role").unwrap_or_default().into(),
+    })
+}
+
+fn parse_id(element: &BytesStart) -> Result<i64, String> {
+    match element.name().as_ref() {
+        b"nd" => parse_id_attribute(element, "ref"),
+        _ => parse_id_attribute(element, "id"),
+    }
+}
+
+fn parse_id_attribute(element: &BytesStart, name: &str) -> Result<i64, String> {
+    let value = attribute(element, name)?;
+    value
+        .parse::<i64>()
+        .map_err(|_| format!("'{}' is not a valid {}", value, name))
+}
+
+fn parse_coordinate(value: &str) -> Result<i32, String> {
+    value
+        .parse::<f64>()
+        .map(|degrees| (degrees * 1e7).round() as i32)
+        .map_err(|_| format!("'{}' is not a valid coordinate", value))
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<String, String> {
+    let element_name = String::from_utf8_lossy(element.name().as_ref()).to_string();
+    for attribute in element.attributes() {
+        let attribute = attribute.map_err(|e| e.to_string())?;
+        if attribute.key.as_ref() == name.as_bytes() {
+            return attribute
+                .unescape_value()
+                .map(|value| value.to_string())
+                .map_err(|e| e.to_string());
+        }
+    }
+    Err(format!("<{}> is missing the attribute '{}'", element_name, name))
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANGES: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<osmChange version="0.6" generator="test">
+  <modify>
+    <node id="1" version="2" lat="49.5" lon="-8.25"/>
+    <way id="10" version="3">
+      <nd ref="1"/>
+      <nd ref="2"/>
+      <tag k="boundary" v="administrative"/>
+    </way>
+  </modify>
+  <create>
+    <relation id="100" version="1">
+      <member type="way" ref="10" role="outer"/>
+      <member type="node" ref="2" role="admin_centre"/>
+      <tag k="name" v="Caf&#233; &amp; Bar"/>
+    </relation>
+  </create>
+  <delete>
+    <node id="2" version="4"/>
+  </delete>
+</osmChange>"#;
+
+    #[test]
+    fn test_parse_osc_reads_actions_and_objects() {
+        let changes = parse_osc(CHANGES.as_bytes()).unwrap();
+
+        let summary: Vec<(Action, OsmId)> = changes.iter().map(|c| (c.action, c.object.id())).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Action::Modify, OsmId::Node(NodeId(1))),
+                (Action::Modify, OsmId::Way(WayId(10))),
+                (Action::Create, OsmId::Relation(RelationId(100))),
+                (Action::Delete, OsmId::Node(NodeId(2))),
+            ]
+        );
+
+        let node = changes[0].object.node().unwrap();
+        assert_eq!((node.decimicro_lat, node.decimicro_lon), (495_000_000, -82_500_000));
+        assert_eq!(changes[1].object.way().unwrap().nodes, vec![NodeId(1), NodeId(2)]);
+        let relation = changes[2].object.relation().unwrap();
+        assert_eq!(relation.tags.get("name").map(|n| n.as_str()), Some("Café & Bar"));
+        assert_eq!(relation.refs[1].role.as_str(), "admin_centre");
+    }
+
+    #[test]
+    fn test_parse_osc_reports_invalid_content() {
+        assert!(
+            parse_osc(r#"<osmChange><node id="1" lat="0" lon="0"/></osmChange>"#.as_bytes())
+                .unwrap_err()
+                .ends_with("object outside of create, modify, or delete")
+        );
+        assert!(
+            parse_osc(r#"<osmChange><modify><way id="x"/></modify></osmChange>"#.as_bytes())
+                .unwrap_err()
+                .ends_with("'x' is not a valid id")
+        );
+        assert!(
+            parse_osc(r#"<osmChange><modify><node id="1" lat="north" lon="0"/></modify></osmChange>"#.as_bytes())
+                .unwrap_err()
+                .ends_with("'north' is not a valid coordinate")
+        );
+    }
+
+    #[test]
+    fn test_apply_replaces_and_removes_objects() {
+        let mut objects = OsmObjects::default();
+        objects.nodes.insert(
+            NodeId(2),
+            Node {
+                id: NodeId(2),
+                tags: Tags::new(),
+                decimicro_lat: 0,
+                decimicro_lon: 0,
+            },
+        );
+        objects.ways.insert(WayId(10), vec![NodeId(2)]);
+
+        apply(&mut objects, parse_osc(CHANGES.as_bytes()).unwrap());
+
+        assert_eq!(objects.ways[&WayId(10)], vec![NodeId(1), NodeId(2)]);
+        assert_eq!(objects.nodes.keys().collect::<Vec<_>>(), vec![&NodeId(1)]);
+        assert!(objects.relations.contains_key(&RelationId(100)));
+    }
+}
@@ -1,45 +1,332 @@
-use osmpbfreader::{Node, NodeId, OsmPbfReader, Relation, RelationId, WayId};
+use osmpbfreader::{Node, NodeId, OsmId, Relation, RelationId, WayId};
+use regex::{Regex, RegexBuilder};
+use sha2::{Digest, Sha256};
+use unicode_normalization::UnicodeNormalization;
 
 use std::collections::{HashMap, HashSet};
-use std::fs::File;
-use std::path::Path;
 use std::time::Instant;
 
+use crate::block_index::BlockIndex;
+use crate::geometry::BoundingBox;
+use crate::region::Region;
 use crate::utils::values_to_set;
 
-type OsmPbfReaderFile = osmpbfreader::OsmPbfReader<std::fs::File>;
+const MEMBER_NODE_ROLES: [&str; 2] = ["admin_centre", "label"];
+const MAX_RELATION_DEPTH: usize = 8;
+const NAME_SELECTION_TAGS: [&str; 2] = ["name", "official_name"];
+const ISO3166_TAGS: [&str; 4] = ["ISO3166-1", "ISO3166-1:alpha2", "ISO3166-1:alpha3", "ISO3166-2"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IdSelection {
+    // only the listed relations are read, regardless of their tags
+    Replace,
+    // the listed relations are read if they also pass the boundary and admin level filter
+    Intersect,
+}
+
+#[derive(Clone, Debug)]
+pub struct RelationFilter {
+    pub min_admin: i8,
+    pub max_admin: i8,
+    pub relation_ids: Option<(HashSet<RelationId>, IdSelection)>,
+    pub name_regex: Option<Regex>,
+    pub iso3166: Option<HashSet<String>>,
+    pub region: Option<Region>,
+}
+
+impl RelationFilter {
+    pub fn matches(&self, relation: &Relation) -> bool {
+        let tag_filter = || {
+            relation.tags.contains("boundary", "administrative")
+                && has_proper_admin_level(relation, &self.min_admin, &self.max_admin)
+        };
+
+        let selected = match &self.relation_ids {
+            None => tag_filter(),
+            Some((ids, IdSelection::Replace)) => ids.contains(&relation.id),
+            Some((ids, IdSelection::Intersect)) => ids.contains(&relation.id) && tag_filter(),
+        };
+
+        selected
+            && self
+                .name_regex
+                .as_ref()
+                .is_none_or(|regex| has_matching_name(relation, regex))
+            && self
+                .iso3166
+                .as_ref()
+                .is_none_or(|codes| has_iso3166_code(relation, codes))
+    }
+
+    // identifies the selection, e.g., so that a cache is only updated with the options it was created with
+    pub fn fingerprint(&self) -> String {
+        let relation_ids = self.relation_ids.as_ref().map(|(ids, selection)| {
+            let mut ids: Vec<i64> = ids.iter().map(|id| id.0).collect();
+            ids.sort_unstable();
+            (ids, *selection)
+        });
+        let iso3166 = self.iso3166.as_ref().map(|codes| {
+            let mut codes: Vec<&String> = codes.iter().collect();
+            codes.sort_unstable();
+            codes
+        });
+        let description = format!(
+            "admin_level={}-{};relation_ids={:?};name_regex={:?};iso3166={:?};region={:?}",
+            self.min_admin,
+            self.max_admin,
+            relation_ids,
+            self.name_regex.as_ref().map(Regex::as_str),
+            iso3166,
+            self.region.as_ref().map(Region::describe)
+        );
+
+        Sha256::digest(description.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+}
+
+// names appear both precomposed and decomposed (e.g., 'ü' vs. 'u' + combining diaeresis), so everything is compared in NFKC
+fn normalize_text(text: &str) -> String {
+    text.nfkc().collect()
+}
+
+pub fn compile_name_regex(pattern: &str) -> Result<Regex, String> {
+    RegexBuilder::new(&normalize_text(pattern))
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+pub fn parse_iso3166_codes(text: &str) -> HashSet<String> {
+    text.split(',')
+        .map(|code| normalize_text(code.trim()).to_uppercase())
+        .filter(|code| !code.is_empty())
+        .collect()
+}
+
+fn has_matching_name(relation: &Relation, regex: &Regex) -> bool {
+    /*
+        besides the name tags themselves, all language variants (name:de, name:en, ...) are considered,
+        as well as the name with its prefix (e.g., 'Stadt Karlsruhe' for name:prefix=Stadt and name=Karlsruhe)
+    */
+    let tags = &relation.tags;
+    let mut candidates: Vec<String> = tags
+        .iter()
+        .filter(|(key, _)| {
+            NAME_SELECTION_TAGS.contains(&key.as_str()) || (key.starts_with("name:") && key.as_str() != "name:prefix")
+        })
+        .map(|(_, value)| value.to_string())
+        .collect();
+    if let (Some(prefix), Some(name)) = (tags.get("name:prefix"), tags.get("name")) {
+        candidates.push(format!("{} {}", prefix, name));
+    }
+
+    candidates
+        .iter()
+        .any(|candidate| regex.is_match(&normalize_text(candidate)))
+}
+
+fn has_iso3166_code(relation: &Relation, codes: &HashSet<String>) -> bool {
+    ISO3166_TAGS
+        .iter()
+        .filter_map(|key| relation.tags.get(*key))
+        .any(|value| codes.contains(&normalize_text(value.trim()).to_uppercase()))
+}
 
 #[derive(Clone)]
 pub struct RelationNodes {
     pub relation: Relation,
     pub nodes: Vec<Vec<Node>>,
+    pub member_nodes: Vec<(String, Node)>,
 }
 
-pub fn read_osm(filename: &str, min_admin: &i8, max_admin: &i8) -> Result<Vec<RelationNodes>, std::io::Error> {
-    let file = File::open(Path::new(filename))?;
-    read_ways_and_relation(file, min_admin, max_admin)
+// the relations, ways, and nodes the selected boundaries are assembled from
+#[derive(Clone, Default)]
+pub struct OsmObjects {
+    pub relations: HashMap<RelationId, Relation>,
+    pub ways: HashMap<WayId, Vec<NodeId>>,
+    pub nodes: HashMap<NodeId, Node>,
 }
 
-fn read_ways_and_relation(file: File, min_admin: &i8, max_admin: &i8) -> Result<Vec<RelationNodes>, std::io::Error> {
-    let mut reader = OsmPbfReader::new(file);
+pub fn read_osm(filename: &str, filter: &RelationFilter) -> Result<Vec<RelationNodes>, std::io::Error> {
+    let objects = read_osm_objects(filename, filter)?;
+    Ok(assemble(&objects, filter))
+}
 
-    let relations = find_admin_boundary_relations(&mut reader, min_admin, max_admin);
+pub fn read_osm_objects(filename: &str, filter: &RelationFilter) -> Result<OsmObjects, std::io::Error> {
+    let index = BlockIndex::new(filename)?;
+    read_ways_and_relation(&index, filter)
+}
 
-    let relation_to_ways = find_ways_for_relation_ids(&relations);
-    let way_to_nodes = find_nodes_for_way_ids(&mut reader, values_to_set(&relation_to_ways));
-    let node_map = find_nodes_for_node_ids(&mut reader, values_to_set(&way_to_nodes));
+pub fn parse_relation_ids(text: &str) -> Result<HashSet<RelationId>, String> {
+    /*
+        ids are separated by commas or whitespace, '#' starts a comment until the end of the line;
+        a leading 'r' (as in r62422) is accepted, since that is how relations are often written
+    */
+    let mut ids = HashSet::new();
+
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default();
+        for token in line.split([',', ' ', '\t']).map(str::trim).filter(|t| !t.is_empty()) {
+            let digits = token.strip_prefix(['r', 'R']).unwrap_or(token);
+            match digits.parse::<i64>() {
+                Ok(id) if id > 0 => ids.insert(RelationId(id)),
+                _ => return Err(format!("line {}: '{}' is not a relation id", line_number + 1, token)),
+            };
+        }
+    }
 
-    let relation_to_nodes = relation_to_ways
+    Ok(ids)
+}
+
+pub fn read_relation_ids_file(filename: &str) -> Result<HashSet<RelationId>, String> {
+    let text = std::fs::read_to_string(filename).map_err(|e| format!("{}: {}", filename, e))?;
+    parse_relation_ids(&text).map_err(|e| format!("{}: {}", filename, e))
+}
+
+fn read_ways_and_relation(index: &BlockIndex, filter: &RelationFilter) -> Result<OsmObjects, std::io::Error> {
+    let mut relations = find_admin_boundary_relations(index, filter)?;
+    report_missing_relation_ids(filter, |id| relations.contains_key(id));
+    let member_relations = find_member_relations(index, &relations)?;
+
+    let member_node_ids = find_member_node_ids(&relations);
+    relations.extend(member_relations);
+    let ways = find_nodes_for_way_ids(index, find_way_ids(&relations))?;
+
+    let mut node_ids = values_to_set(&ways);
+    node_ids.extend(member_node_ids);
+    let nodes = find_nodes_for_node_ids(index, node_ids)?;
+
+    Ok(OsmObjects { relations, ways, nodes })
+}
+
+pub fn assemble(objects: &OsmObjects, filter: &RelationFilter) -> Vec<RelationNodes> {
+    let relations = find_boundaries(objects, filter);
+    let relation_to_ways = find_ways_for_relation_ids(&relations, &objects.relations);
+    let relation_to_member_nodes = find_member_nodes_for_relation_ids(&relations);
+
+    relation_to_ways
         .iter()
+        .filter(|(_, ways)| {
+            filter
+                .region
+                .as_ref()
+                .is_none_or(|region| may_be_in_region(ways, &objects.ways, &objects.nodes, region))
+        })
         .map(|(r_id, ways)| {
-            let nodes = replace_way_id_with_node_ids(ways, &way_to_nodes);
-            let nodes = replace_node_id_with_node(nodes, &node_map);
+            let nodes = replace_way_id_with_node_ids(ways, &objects.ways);
+            let nodes = replace_node_id_with_node(nodes, &objects.nodes);
             let relation = relations.get(r_id).expect("Relation not found").clone();
-            RelationNodes { relation, nodes }
+            let member_nodes = relation_to_member_nodes[r_id]
+                .iter()
+                .filter_map(|(role, node_id)| objects.nodes.get(node_id).map(|node| (role.clone(), node.clone())))
+                .collect();
+            RelationNodes {
+                relation,
+                nodes,
+                member_nodes,
+            }
         })
+        .collect()
+}
+
+pub fn select(objects: &OsmObjects, filter: &RelationFilter) -> (OsmObjects, HashSet<OsmId>) {
+    /*
+        keeps what reading a file with the filter would have found: the boundaries, their member relations,
+        and the ways and nodes these reference; returns the referenced objects that are not available as well
+    */
+    let mut missing = HashSet::new();
+    let mut relations = find_boundaries(objects, filter);
+    let member_node_ids = find_member_node_ids(&relations);
+
+    let mut pending: Vec<RelationId> = relations
+        .values()
+        .flat_map(extract_relation_ids_from_relation)
         .collect();
+    while let Some(relation_id) = pending.pop() {
+        if relations.contains_key(&relation_id) {
+            continue;
+        }
+        match objects.relations.get(&relation_id) {
+            Some(relation) => {
+                pending.extend(extract_relation_ids_from_relation(relation));
+                relations.insert(relation_id, relation.clone());
+            }
+            None => {
+                missing.insert(OsmId::Relation(relation_id));
+            }
+        }
+    }
+
+    let mut ways = HashMap::new();
+    for way_id in find_way_ids(&relations) {
+        match objects.ways.get(&way_id) {
+            Some(node_ids) => {
+                ways.insert(way_id, node_ids.clone());
+            }
+            None => {
+                missing.insert(OsmId::Way(way_id));
+            }
+        }
+    }
+
+    let mut node_ids = values_to_set(&ways);
+    node_ids.extend(member_node_ids);
+    let mut nodes = HashMap::new();
+    for node_id in node_ids {
+        match objects.nodes.get(&node_id) {
+            Some(node) => {
+                nodes.insert(node_id, node.clone());
+            }
+            None => {
+                missing.insert(OsmId::Node(node_id));
+            }
+        }
+    }
+
+    let selected = OsmObjects { relations, ways, nodes };
+    (selected, missing)
+}
 
-    Ok(relation_to_nodes)
+fn find_boundaries(objects: &OsmObjects, filter: &RelationFilter) -> HashMap<RelationId, Relation> {
+    objects
+        .relations
+        .iter()
+        .filter(|(_, relation)| filter.matches(relation))
+        .map(|(relation_id, relation)| (*relation_id, relation.clone()))
+        .collect()
+}
+
+// the ways of all relations, i.e., of the boundaries as well as of their member relations
+fn find_way_ids(relations: &HashMap<RelationId, Relation>) -> HashSet<WayId> {
+    relations.values().flat_map(extract_way_ids_from_relation).collect()
+}
+
+fn find_member_node_ids(relations: &HashMap<RelationId, Relation>) -> HashSet<NodeId> {
+    relations
+        .values()
+        .flat_map(extract_member_node_ids_from_relation)
+        .map(|(_, node_id)| node_id)
+        .collect()
+}
+
+// coarse spatial selection on the node coordinates, so that rings are not assembled for boundaries far away
+fn may_be_in_region(
+    way_ids: &[WayId],
+    way_to_nodes: &HashMap<WayId, Vec<NodeId>>,
+    node_map: &HashMap<NodeId, Node>,
+    region: &Region,
+) -> bool {
+    let coordinates = way_ids
+        .iter()
+        .filter_map(|way_id| way_to_nodes.get(way_id))
+        .flatten()
+        .filter_map(|node_id| node_map.get(node_id))
+        .map(|node| (node.lon(), node.lat()));
+
+    BoundingBox::of_coordinates(coordinates).is_some_and(|bbox| region.may_select(&bbox))
 }
 
 fn has_proper_admin_level(relation: &Relation, min_admin: &i8, max_admin: &i8) -> bool {
@@ -56,6 +343,53 @@ fn extract_way_ids_from_relation(relation: &Relation) -> Vec<WayId> {
     relation.refs.iter().filter_map(|r| r.member.way()).collect()
 }
 
+// subareas are boundaries on their own and not part of the geometry
+fn extract_relation_ids_from_relation(relation: &Relation) -> Vec<RelationId> {
+    relation
+        .refs
+        .iter()
+        .filter(|r| r.role != "subarea")
+        .filter_map(|r| r.member.relation())
+        .collect()
+}
+
+fn collect_way_ids_recursively<'a>(
+    relation: &'a Relation,
+    resolve: &dyn Fn(&RelationId) -> Option<&'a Relation>,
+    path: &mut Vec<RelationId>,
+    way_ids: &mut Vec<WayId>,
+) {
+    way_ids.extend(extract_way_ids_from_relation(relation));
+    path.push(relation.id);
+
+    for member_id in extract_relation_ids_from_relation(relation) {
+        if path.contains(&member_id) {
+            println!(
+                "warning: relation {} references relation {} in a cycle, skipped",
+                relation.id.0, member_id.0
+            );
+        } else if path.len() > MAX_RELATION_DEPTH {
+            println!(
+                "warning: relation {} is nested deeper than {} levels, skipped",
+                member_id.0, MAX_RELATION_DEPTH
+            );
+        } else if let Some(member) = resolve(&member_id) {
+            collect_way_ids_recursively(member, resolve, path, way_ids);
+        }
+    }
+
+    path.pop();
+}
+
+fn extract_member_node_ids_from_relation(relation: &Relation) -> Vec<(String, NodeId)> {
+    relation
+        .refs
+        .iter()
+        .filter(|r| MEMBER_NODE_ROLES.contains(&r.role.as_str()))
+        .filter_map(|r| r.member.node().map(|node_id| (r.role.to_string(), node_id)))
+        .collect()
+}
+
 fn replace_way_id_with_node_ids(
     way_ids: &[WayId],
     way_id_to_node_ids: &HashMap<WayId, Vec<NodeId>>,
@@ -80,22 +414,32 @@ fn replace_node_id_with_node(v_node_ids: Vec<Vec<NodeId>>, node_id_to_node: &Has
         .collect()
 }
 
+pub fn report_missing_relation_ids(filter: &RelationFilter, is_found: impl Fn(&RelationId) -> bool) {
+    if let Some((ids, _)) = &filter.relation_ids {
+        let mut missing: Vec<i64> = ids.iter().filter(|id| !is_found(id)).map(|id| id.0).collect();
+        missing.sort_unstable();
+
+        if !missing.is_empty() {
+            let missing: Vec<std::string::String> = missing.iter().map(|id| id.to_string()).collect();
+            println!(
+                "warning: {} of the selected relations were not found or filtered out: {}",
+                missing.len(),
+                missing.join(",")
+            );
+        }
+    }
+}
+
 fn find_admin_boundary_relations(
-    pbf: &mut OsmPbfReaderFile,
-    min_admin: &i8,
-    max_admin: &i8,
-) -> HashMap<RelationId, Relation> {
+    index: &BlockIndex,
+    filter: &RelationFilter,
+) -> Result<HashMap<RelationId, Relation>, std::io::Error> {
     let now = Instant::now();
     println!("parsing relations...");
 
-    let relation_id_to_relation: HashMap<RelationId, Relation> = pbf
-        .par_iter()
-        .map(Result::unwrap)
-        .filter(|obj| obj.is_relation())
-        .filter(|obj| obj.relation().unwrap().tags.contains("boundary", "administrative"))
-        .filter(|obj| has_proper_admin_level(obj.relation().unwrap(), min_admin, max_admin))
-        .map(|obj| obj.relation().unwrap().clone())
-        .map(|relation| (relation.id, relation))
+    let relation_id_to_relation: HashMap<RelationId, Relation> = index
+        .relations(|relation| filter.matches(&relation).then_some((relation.id, relation)))?
+        .into_iter()
         .collect();
 
     println!(
@@ -103,52 +447,120 @@ fn find_admin_boundary_relations(
         relation_id_to_relation.len(),
         now.elapsed().as_secs()
     );
-    relation_id_to_relation
+    Ok(relation_id_to_relation)
+}
+
+fn find_member_relations(
+    index: &BlockIndex,
+    relation_id_to_relation: &HashMap<RelationId, Relation>,
+) -> Result<HashMap<RelationId, Relation>, std::io::Error> {
+    /*
+        relations referenced by the boundaries are read level by level, i.e., every level of nesting
+        takes another pass over the relation blocks; relations that are already known are not requested again
+    */
+    let mut member_relations: HashMap<RelationId, Relation> = HashMap::new();
+    let is_known = |id: &RelationId, member_relations: &HashMap<RelationId, Relation>| {
+        relation_id_to_relation.contains_key(id) || member_relations.contains_key(id)
+    };
+
+    let mut missing: HashSet<RelationId> = relation_id_to_relation
+        .values()
+        .flat_map(extract_relation_ids_from_relation)
+        .filter(|id| !is_known(id, &member_relations))
+        .collect();
+
+    for _ in 0..MAX_RELATION_DEPTH {
+        if missing.is_empty() {
+            break;
+        }
+
+        let found = find_relations_for_relation_ids(index, &missing)?;
+        member_relations.extend(found.iter().map(|(id, relation)| (*id, relation.clone())));
+        missing = found
+            .values()
+            .flat_map(extract_relation_ids_from_relation)
+            .filter(|id| !is_known(id, &member_relations))
+            .collect();
+    }
+
+    Ok(member_relations)
+}
+
+fn find_relations_for_relation_ids(
+    index: &BlockIndex,
+    relation_ids: &HashSet<RelationId>,
+) -> Result<HashMap<RelationId, Relation>, std::io::Error> {
+    let now = Instant::now();
+
+    println!("parsing {} member relations...", relation_ids.len());
+    let relation_id_to_relation: HashMap<RelationId, Relation> = index
+        .relations(|relation| relation_ids.contains(&relation.id).then_some((relation.id, relation)))?
+        .into_iter()
+        .collect();
+
+    println!("parsing member relations finished! {}s", now.elapsed().as_secs());
+    Ok(relation_id_to_relation)
 }
 
 fn find_ways_for_relation_ids(
     relation_id_to_relation: &HashMap<RelationId, Relation>,
+    member_relations: &HashMap<RelationId, Relation>,
 ) -> HashMap<RelationId, Vec<WayId>> {
+    let resolve = |id: &RelationId| relation_id_to_relation.get(id).or_else(|| member_relations.get(id));
+
     relation_id_to_relation
         .iter()
-        .map(|(relation_id, relation)| (*relation_id, extract_way_ids_from_relation(relation)))
+        .map(|(relation_id, relation)| {
+            let mut way_ids = Vec::new();
+            collect_way_ids_recursively(relation, &resolve, &mut Vec::new(), &mut way_ids);
+
+            // a way can be reached through several member relations, but is only needed once
+            let mut seen = HashSet::new();
+            way_ids.retain(|way_id| seen.insert(*way_id));
+            (*relation_id, way_ids)
+        })
         .collect()
 }
 
-fn find_nodes_for_way_ids(pbf: &mut OsmPbfReaderFile, way_ids: HashSet<WayId>) -> HashMap<WayId, Vec<NodeId>> {
+fn find_member_nodes_for_relation_ids(
+    relation_id_to_relation: &HashMap<RelationId, Relation>,
+) -> HashMap<RelationId, Vec<(String, NodeId)>> {
+    relation_id_to_relation
+        .iter()
+        .map(|(relation_id, relation)| (*relation_id, extract_member_node_ids_from_relation(relation)))
+        .collect()
+}
+
+fn find_nodes_for_way_ids(
+    index: &BlockIndex,
+    way_ids: HashSet<WayId>,
+) -> Result<HashMap<WayId, Vec<NodeId>>, std::io::Error> {
     let now = Instant::now();
 
     println!("parsing ways...");
-    let _rewind_result = pbf.rewind();
-    let way_to_nodes: HashMap<WayId, Vec<NodeId>> = pbf
-        .par_iter()
-        .map(Result::unwrap)
-        .filter(|obj| obj.is_way())
-        .filter(|obj| way_ids.contains(&obj.way().unwrap().id))
-        .map(|obj| obj.way().unwrap().clone())
-        .map(|way| (way.id, way.nodes))
+    let way_to_nodes: HashMap<WayId, Vec<NodeId>> = index
+        .ways(|way| way_ids.contains(&way.id).then_some((way.id, way.nodes)))?
+        .into_iter()
         .collect();
 
     println!("parsing ways finished! {}s", now.elapsed().as_secs());
-    way_to_nodes
+    Ok(way_to_nodes)
 }
 
-fn find_nodes_for_node_ids(pbf: &mut OsmPbfReaderFile, node_ids: HashSet<NodeId>) -> HashMap<NodeId, Node> {
+fn find_nodes_for_node_ids(
+    index: &BlockIndex,
+    node_ids: HashSet<NodeId>,
+) -> Result<HashMap<NodeId, Node>, std::io::Error> {
     let now = Instant::now();
 
     println!("parsing nodes...");
-    let _rewind_result = pbf.rewind();
-    let node_id_to_node: HashMap<NodeId, Node> = pbf
-        .par_iter()
-        .map(Result::unwrap)
-        .filter(|obj| obj.is_node())
-        .filter(|obj| node_ids.contains(&obj.node().unwrap().id))
-        .map(|obj| obj.node().unwrap().clone())
-        .map(|node| (node.id, node))
+    let node_id_to_node: HashMap<NodeId, Node> = index
+        .nodes(|node| node_ids.contains(&node.id).then_some((node.id, node)))?
+        .into_iter()
         .collect();
 
     println!("parsing nodes finished! {}s", now.elapsed().as_secs());
-    node_id_to_node
+    Ok(node_id_to_node)
 }
 
 // ////////////////////////////////////
@@ -160,7 +572,7 @@ fn find_nodes_for_node_ids(pbf: &mut OsmPbfReaderFile, node_ids: HashSet<NodeId>
 #[cfg(test)]
 mod tests {
     use super::*;
-    use osmpbfreader::Tags;
+    use osmpbfreader::{OsmId, Ref, Tags};
     use std::iter::FromIterator;
 
     use smartstring::alias::String;
@@ -168,7 +580,7 @@ mod tests {
     #[test]
     fn test_empty_relation_has_not_proper_admin_level() {
         let relation = create_relation(vec![]);
-        assert_eq!(has_proper_admin_level(&relation, &1, &8), false);
+        assert!(!has_proper_admin_level(&relation, &1, &8));
     }
 
     #[test]
@@ -178,7 +590,7 @@ mod tests {
             String::from("admin_level"),
             String::from((max_admin_level + 1).to_string()),
         )]);
-        assert_eq!(has_proper_admin_level(&relation, &1, &max_admin_level), false);
+        assert!(!has_proper_admin_level(&relation, &1, &max_admin_level));
     }
 
     #[test]
@@ -188,14 +600,14 @@ mod tests {
             String::from("admin_level"),
             String::from((max_admin_level).to_string()),
         )]);
-        assert_eq!(has_proper_admin_level(&relation, &1, &max_admin_level), true);
+        assert!(has_proper_admin_level(&relation, &1, &max_admin_level));
     }
 
     #[test]
     fn test_min_admin_level_filters_out() {
         let min_admin_level = 1;
         let relation = create_relation(vec![(String::from("admin_level"), String::from((0).to_string()))]);
-        assert_eq!(has_proper_admin_level(&relation, &min_admin_level, &8), false);
+        assert!(!has_proper_admin_level(&relation, &min_admin_level, &8));
     }
 
     #[test]
@@ -216,20 +628,283 @@ mod tests {
             String::from((min_admin_level + 1).to_string()),
         )]);
 
+        assert!(!has_proper_admin_level(
+            &relation_too_little,
+            &min_admin_level,
+            &max_admin_level
+        ));
+        assert!(has_proper_admin_level(
+            &relation_exact,
+            &min_admin_level,
+            &max_admin_level
+        ));
+        assert!(!has_proper_admin_level(
+            &relation_too_big,
+            &min_admin_level,
+            &max_admin_level
+        ));
+    }
+
+    #[test]
+    fn test_extract_member_node_ids_only_keeps_admin_centre_and_label() {
+        let mut relation = create_relation(vec![]);
+        relation.refs = vec![
+            create_ref(OsmId::Node(NodeId(1)), "admin_centre"),
+            create_ref(OsmId::Node(NodeId(2)), "label"),
+            create_ref(OsmId::Node(NodeId(3)), ""),
+            create_ref(OsmId::Way(WayId(4)), "outer"),
+            create_ref(OsmId::Relation(RelationId(5)), "label"),
+        ];
+
+        let result = extract_member_node_ids_from_relation(&relation);
+
+        assert_eq!(
+            result,
+            vec![
+                (std::string::String::from("admin_centre"), NodeId(1)),
+                (std::string::String::from("label"), NodeId(2))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collect_way_ids_resolves_nested_relations_but_not_subareas() {
+        let relations: HashMap<RelationId, Relation> = vec![
+            create_relation_with_refs(
+                1,
+                vec![
+                    create_ref(OsmId::Way(WayId(10)), "outer"),
+                    create_ref(OsmId::Relation(RelationId(2)), "outer"),
+                    create_ref(OsmId::Relation(RelationId(4)), "subarea"),
+                ],
+            ),
+            create_relation_with_refs(
+                2,
+                vec![
+                    create_ref(OsmId::Relation(RelationId(3)), ""),
+                    create_ref(OsmId::Way(WayId(10)), "outer"),
+                ],
+            ),
+            create_relation_with_refs(3, vec![create_ref(OsmId::Way(WayId(30)), "outer")]),
+            create_relation_with_refs(4, vec![create_ref(OsmId::Way(WayId(40)), "outer")]),
+        ]
+        .into_iter()
+        .map(|r| (r.id, r))
+        .collect();
+        let selected: HashMap<RelationId, Relation> = vec![(RelationId(1), relations[&RelationId(1)].clone())]
+            .into_iter()
+            .collect();
+
+        let result = find_ways_for_relation_ids(&selected, &relations);
+
+        assert_eq!(result[&RelationId(1)], vec![WayId(10), WayId(30)]);
+    }
+
+    #[test]
+    fn test_collect_way_ids_stops_at_cycles() {
+        let relations: HashMap<RelationId, Relation> = vec![
+            create_relation_with_refs(
+                1,
+                vec![
+                    create_ref(OsmId::Way(WayId(10)), "outer"),
+                    create_ref(OsmId::Relation(RelationId(2)), ""),
+                ],
+            ),
+            create_relation_with_refs(
+                2,
+                vec![
+                    create_ref(OsmId::Way(WayId(20)), "outer"),
+                    create_ref(OsmId::Relation(RelationId(1)), ""),
+                ],
+            ),
+        ]
+        .into_iter()
+        .map(|r| (r.id, r))
+        .collect();
+
+        let resolve = |id: &RelationId| relations.get(id);
+        let mut way_ids = Vec::new();
+        collect_way_ids_recursively(&relations[&RelationId(1)], &resolve, &mut Vec::new(), &mut way_ids);
+
+        assert_eq!(way_ids, vec![WayId(10), WayId(20)]);
+    }
+
+    #[test]
+    fn test_select_keeps_referenced_objects_and_reports_missing_ones() {
+        let mut boundary = create_relation_with_refs(
+            1,
+            vec![
+                create_ref(OsmId::Way(WayId(10)), "outer"),
+                create_ref(OsmId::Relation(RelationId(2)), ""),
+                create_ref(OsmId::Node(NodeId(5)), "admin_centre"),
+            ],
+        );
+        boundary.tags = create_relation(vec![
+            (String::from("boundary"), String::from("administrative")),
+            (String::from("admin_level"), String::from("4")),
+        ])
+        .tags;
+        let node = |id: i64| Node {
+            id: NodeId(id),
+            tags: Tags::new(),
+            decimicro_lat: 0,
+            decimicro_lon: 0,
+        };
+
+        let objects = OsmObjects {
+            relations: vec![
+                boundary,
+                create_relation_with_refs(2, vec![create_ref(OsmId::Way(WayId(20)), "outer")]),
+                create_relation_with_refs(3, vec![create_ref(OsmId::Way(WayId(30)), "outer")]),
+            ]
+            .into_iter()
+            .map(|r| (r.id, r))
+            .collect(),
+            ways: vec![
+                (WayId(10), vec![NodeId(1), NodeId(2)]),
+                (WayId(20), vec![NodeId(2), NodeId(3)]),
+                (WayId(30), vec![NodeId(4)]),
+            ]
+            .into_iter()
+            .collect(),
+            nodes: vec![node(1), node(2), node(4)].into_iter().map(|n| (n.id, n)).collect(),
+        };
+
+        let (selected, missing) = select(&objects, &create_filter());
+
+        let mut relation_ids: Vec<i64> = selected.relations.keys().map(|id| id.0).collect();
+        relation_ids.sort_unstable();
+        let mut way_ids: Vec<i64> = selected.ways.keys().map(|id| id.0).collect();
+        way_ids.sort_unstable();
+        let mut node_ids: Vec<i64> = selected.nodes.keys().map(|id| id.0).collect();
+        node_ids.sort_unstable();
         assert_eq!(
-            has_proper_admin_level(&relation_too_little, &min_admin_level, &max_admin_level),
-            false
+            (relation_ids, way_ids, node_ids),
+            (vec![1, 2], vec![10, 20], vec![1, 2])
         );
         assert_eq!(
-            has_proper_admin_level(&relation_exact, &min_admin_level, &max_admin_level),
-            true
+            missing,
+            HashSet::from_iter(vec![OsmId::Node(NodeId(3)), OsmId::Node(NodeId(5))])
         );
+    }
+
+    #[test]
+    fn test_parse_relation_ids_accepts_separators_comments_and_prefix() {
+        let ids = parse_relation_ids("62422,2793104\n# launch cities\nr51477  62611 # Baden-Württemberg\n\n").unwrap();
+
+        let expected: HashSet<RelationId> = vec![62422, 2793104, 51477, 62611].into_iter().map(RelationId).collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn test_parse_relation_ids_reports_line_of_invalid_id() {
+        let result = parse_relation_ids("62422\nberlin");
+
         assert_eq!(
-            has_proper_admin_level(&relation_too_big, &min_admin_level, &max_admin_level),
-            false
+            result,
+            Err(std::string::String::from("line 2: 'berlin' is not a relation id"))
         );
     }
 
+    #[test]
+    fn test_relation_filter_replaces_or_intersects_tag_filter() {
+        let mut relation = create_relation(vec![(String::from("boundary"), String::from("maritime"))]);
+        relation.id = RelationId(62422);
+
+        let mut filter = create_filter();
+        assert!(!filter.matches(&relation));
+
+        filter.relation_ids = Some((vec![RelationId(62422)].into_iter().collect(), IdSelection::Replace));
+        assert!(filter.matches(&relation));
+
+        filter.relation_ids = Some((vec![RelationId(62422)].into_iter().collect(), IdSelection::Intersect));
+        assert!(!filter.matches(&relation));
+
+        relation.tags = Tags::from_iter(vec![
+            (String::from("boundary"), String::from("administrative")),
+            (String::from("admin_level"), String::from("6")),
+        ]);
+        assert!(filter.matches(&relation));
+    }
+
+    #[test]
+    fn test_name_regex_matches_case_insensitive_and_normalized() {
+        // 'Baden-Württemberg' with a decomposed 'ü' (u + combining diaeresis)
+        let relation = create_relation(vec![(String::from("name"), String::from("Baden-Wu\u{308}rttemberg"))]);
+
+        let regex = compile_name_regex("^baden-württemberg$").unwrap();
+        assert!(has_matching_name(&relation, &regex));
+
+        let regex = compile_name_regex("^Bayern$").unwrap();
+        assert!(!has_matching_name(&relation, &regex));
+    }
+
+    #[test]
+    fn test_name_regex_matches_prefixed_name_and_variants() {
+        let relation = create_relation(vec![
+            (String::from("name"), String::from("Karlsruhe")),
+            (String::from("name:prefix"), String::from("Stadt")),
+            (String::from("name:fr"), String::from("Carlsruhe")),
+        ]);
+
+        assert!(has_matching_name(&relation, &compile_name_regex("^Stadt ").unwrap()));
+        assert!(has_matching_name(&relation, &compile_name_regex("^carls").unwrap()));
+        assert!(!has_matching_name(&relation, &compile_name_regex("^Stadt$").unwrap()));
+    }
+
+    #[test]
+    fn test_iso3166_filter_matches_country_and_subdivision_codes() {
+        let codes = parse_iso3166_codes(" de-bw,DE-BY, at");
+        let subdivision = create_relation(vec![(String::from("ISO3166-2"), String::from("DE-BW"))]);
+        let country = create_relation(vec![(String::from("ISO3166-1:alpha2"), String::from("AT"))]);
+        let other = create_relation(vec![(String::from("ISO3166-2"), String::from("DE-HE"))]);
+
+        assert!(has_iso3166_code(&subdivision, &codes));
+        assert!(has_iso3166_code(&country, &codes));
+        assert!(!has_iso3166_code(&other, &codes));
+        assert!(!has_iso3166_code(&create_relation(vec![]), &codes));
+    }
+
+    fn create_filter() -> RelationFilter {
+        RelationFilter {
+            min_admin: 1,
+            max_admin: 8,
+            relation_ids: None,
+            name_regex: None,
+            iso3166: None,
+            region: None,
+        }
+    }
+
+    #[test]
+    fn test_fingerprint_depends_only_on_the_selection() {
+        let mut filter = create_filter();
+        filter.relation_ids = Some(((1..100).map(RelationId).collect(), IdSelection::Replace));
+        let mut same = create_filter();
+        same.relation_ids = Some(((1..100).rev().map(RelationId).collect(), IdSelection::Replace));
+        let mut other = create_filter();
+        other.relation_ids = Some(((1..100).map(RelationId).collect(), IdSelection::Intersect));
+
+        assert_eq!(filter.fingerprint(), same.fingerprint());
+        assert_ne!(filter.fingerprint(), other.fingerprint());
+        assert_ne!(create_filter().fingerprint(), filter.fingerprint());
+    }
+
+    fn create_relation_with_refs(id: i64, refs: Vec<Ref>) -> Relation {
+        Relation {
+            id: RelationId(id),
+            tags: Tags::new(),
+            refs,
+        }
+    }
+
+    fn create_ref(member: OsmId, role: &str) -> Ref {
+        Ref {
+            member,
+            role: String::from(role),
+        }
+    }
+
     fn create_relation(tags_pairs: Vec<(String, String)>) -> Relation {
         Relation {
             id: RelationId(123),
@@ -1,4 +1,4 @@
-use std::fs::File;
+use std::fs::{create_dir_all, File};
 use std::io::{self};
 use std::path::Path;
 
@@ -26,6 +26,10 @@ impl FileCreator {
             }
         }
 
+        if let Some(parent) = filename.parent() {
+            create_dir_all(parent)?;
+        }
+
         File::create(filename)
     }
 
@@ -0,0 +1,44 @@
+use crate::converter::Polygon;
+use crate::geometry::BoundingBox;
+use crate::output::output_handler::FileWriter;
+
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::Error;
+
+pub struct BboxWriter {}
+
+impl FileWriter for BboxWriter {
+    fn write_to_file(&self, file: &mut File, polygon: &Polygon) -> std::io::Result<()> {
+        let bbox = BoundingBox::of_polygon_wrapping(polygon).ok_or_else(|| Error::other("Boundary has no points"))?;
+        file.write_all(render_bbox(&bbox).as_bytes())
+    }
+}
+
+// left,bottom,right,top as expected by osmium's --bbox option
+fn render_bbox(bbox: &BoundingBox) -> String {
+    format!("{},{},{},{}\n", bbox.min_lon, bbox.min_lat, bbox.max_lon, bbox.max_lat)
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_bbox_uses_osmium_order() {
+        let bbox = BoundingBox {
+            min_lon: 8.2773,
+            min_lat: 48.9404,
+            max_lon: 8.5417,
+            max_lat: 49.0912,
+        };
+
+        assert_eq!(render_bbox(&bbox), "8.2773,48.9404,8.5417,49.0912\n");
+    }
+}
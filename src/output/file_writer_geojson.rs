@@ -1,32 +1,47 @@
-use crate::converter::{Point, Polygon};
+use crate::converter::{PlaceNode, Point, Polygon};
+use crate::geometry::{crosses_antimeridian, split_at_antimeridian, BoundingBox};
+use crate::metrics::Metrics;
 use crate::output::output_handler::FileWriter;
+use crate::output::TagSelection;
 
 use geo_types::Polygon as GeoPolygon;
 use geo_types::{Coord, LineString, MultiPolygon};
-use geojson::{Feature, Geometry};
+use geojson::{Feature, FeatureCollection, Geometry};
 
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::{Error, ErrorKind};
+use std::io::Error;
 
-use serde_json::{to_value, Map};
+use serde_json::{json, to_value, Map};
 
-pub struct GeoJsonWriter {}
+pub struct GeoJsonWriter {
+    pub tag_selection: TagSelection,
+    pub split_antimeridian: bool,
+}
 
 impl FileWriter for GeoJsonWriter {
     fn write_to_file(&self, file: &mut File, polygon: &Polygon) -> std::io::Result<()> {
-        let feature = convert_polygon_to_geojson_feature(polygon)
-            .map_err(|_| Error::new(ErrorKind::Other, "Error in converting Polygon to GeoJSON"))?;
+        let feature = convert_polygon_to_geojson_feature(polygon, &self.tag_selection, self.split_antimeridian)
+            .map_err(|_| Error::other("Error in converting Polygon to GeoJSON"))?;
         file.write_all(feature.to_string().as_bytes())
     }
 }
 
-fn convert_polygon_to_geojson_feature(polygon: &Polygon) -> Result<Feature, ()> {
-    let properties = create_properties(polygon);
-    let polygons = convert_polygon_to_geo_polygons(polygon);
-
-    convert_to_geometry(polygons).map(|geometry| Feature {
-        bbox: None,
+fn convert_polygon_to_geojson_feature(
+    polygon: &Polygon,
+    tag_selection: &TagSelection,
+    split_antimeridian: bool,
+) -> Result<Feature, ()> {
+    let properties = create_properties(polygon, tag_selection);
+
+    let geometry = if split_antimeridian && polygon.points.iter().any(|ring| crosses_antimeridian(ring)) {
+        Ok(Geometry::new(geojson::Value::from(&split_at_antimeridian(polygon))))
+    } else {
+        convert_to_geometry(convert_polygon_to_geo_polygons(polygon))
+    };
+
+    geometry.map(|geometry| Feature {
+        bbox: BoundingBox::of_polygon_wrapping(polygon).map(|b| vec![b.min_lon, b.min_lat, b.max_lon, b.max_lat]),
         geometry: Some(geometry),
         id: None,
         properties: Some(properties),
@@ -34,14 +49,98 @@ fn convert_polygon_to_geojson_feature(polygon: &Polygon) -> Result<Feature, ()>
     })
 }
 
-fn create_properties(polygon: &Polygon) -> Map<String, serde_json::Value> {
+fn create_properties(polygon: &Polygon, tag_selection: &TagSelection) -> Map<String, serde_json::Value> {
     let mut properties = Map::new();
+    // selected tags are inserted first, so that they cannot replace the computed properties
+    for (key, value) in polygon.tags.iter().filter(|(k, _)| tag_selection.is_selected(k)) {
+        properties.insert(key.clone(), to_value(value).unwrap());
+    }
     properties.insert(String::from("name"), to_value(&polygon.name).unwrap());
     properties.insert(String::from("admin_level"), to_value(polygon.admin_level).unwrap());
     properties.insert(String::from("relation_id"), to_value(polygon.relation_id).unwrap());
+    properties.insert(
+        String::from("parent_relation_id"),
+        to_value(polygon.parent_relation_id()).unwrap(),
+    );
+    properties.insert(String::from("ancestors"), create_ancestors(polygon));
+    properties.insert(String::from("label_point"), create_place_node(polygon.label.as_ref()));
+    properties.insert(
+        String::from("admin_centre"),
+        create_place_node(polygon.admin_centre.as_ref()),
+    );
+
+    let metrics = Metrics::of_polygon(polygon);
+    let to_coordinates = |point: Option<(f64, f64)>| to_value(point.map(|(lon, lat)| vec![lon, lat])).unwrap();
+    properties.insert(String::from("area_km2"), to_value(metrics.area_km2).unwrap());
+    properties.insert(String::from("perimeter_km"), to_value(metrics.perimeter_km).unwrap());
+    properties.insert(String::from("centroid"), to_coordinates(metrics.centroid));
+    properties.insert(
+        String::from("pole_of_inaccessibility"),
+        to_coordinates(metrics.pole_of_inaccessibility),
+    );
     properties
 }
 
+fn create_ancestors(polygon: &Polygon) -> serde_json::Value {
+    serde_json::Value::Array(
+        polygon
+            .ancestors
+            .iter()
+            .map(|a| json!({"relation_id": a.relation_id, "name": a.name, "admin_level": a.admin_level}))
+            .collect(),
+    )
+}
+
+fn create_place_node(place_node: Option<&PlaceNode>) -> serde_json::Value {
+    match place_node {
+        None => serde_json::Value::Null,
+        Some(node) => json!({
+            "node_id": node.node_id,
+            "coordinates": [node.lon, node.lat],
+            "name": node.name,
+            "place": node.place,
+            "population": node.population,
+        }),
+    }
+}
+
+// one point feature per admin_centre and label node of the boundaries
+pub fn render_points(polygons: &[Polygon]) -> FeatureCollection {
+    let features = polygons
+        .iter()
+        .flat_map(|polygon| {
+            vec![("admin_centre", &polygon.admin_centre), ("label", &polygon.label)]
+                .into_iter()
+                .filter_map(move |(role, node)| node.as_ref().map(|node| (role, node, polygon)))
+        })
+        .map(|(role, node, polygon)| {
+            let mut properties = Map::new();
+            properties.insert(String::from("role"), to_value(role).unwrap());
+            properties.insert(String::from("node_id"), to_value(node.node_id).unwrap());
+            properties.insert(String::from("name"), to_value(&node.name).unwrap());
+            properties.insert(String::from("place"), to_value(&node.place).unwrap());
+            properties.insert(String::from("population"), to_value(&node.population).unwrap());
+            properties.insert(String::from("relation_id"), to_value(polygon.relation_id).unwrap());
+            properties.insert(String::from("boundary_name"), to_value(&polygon.name).unwrap());
+            properties.insert(String::from("admin_level"), to_value(polygon.admin_level).unwrap());
+
+            Feature {
+                bbox: None,
+                geometry: Some(Geometry::new(geojson::Value::Point(vec![node.lon, node.lat]))),
+                id: None,
+                properties: Some(properties),
+                foreign_members: None,
+            }
+        })
+        .collect();
+
+    FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    }
+}
+
 fn convert_to_geometry(polygons: Vec<GeoPolygon<f32>>) -> Result<Geometry, ()> {
     match polygons.len() {
         0 => Err(()),
@@ -72,6 +171,7 @@ fn convert_to_linestring(points: &[Point]) -> LineString<f32> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::converter::Ancestor;
     use geojson::Value;
     use std::matches;
 
@@ -86,6 +186,7 @@ mod tests {
             points: vec![vec![p1.clone(), p2.clone(), p3.clone()]],
             relation_id: 1,
             admin_level: 1,
+            ..Default::default()
         };
 
         let result = convert_polygon_to_geo_polygons(&single_polygon);
@@ -122,6 +223,7 @@ mod tests {
             ],
             relation_id: 1,
             admin_level: 1,
+            ..Default::default()
         };
 
         let result = convert_polygon_to_geo_polygons(&poly);
@@ -205,6 +307,57 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_convert_polygon_to_geojson_feature_contains_bbox() {
+        let polygon = Polygon {
+            name: "barfoo".to_string(),
+            points: vec![vec![
+                Point { lat: 1.0, lon: 1.0 },
+                Point { lat: 2.0, lon: 10.0 },
+                Point { lat: 3.0, lon: 100.0 },
+            ]],
+            relation_id: 1,
+            admin_level: 1,
+            ..Default::default()
+        };
+
+        let result = convert_polygon_to_geojson_feature(&polygon, &TagSelection::Nothing, false).unwrap();
+
+        assert_eq!(result.bbox, Some(vec![1.0, 1.0, 100.0, 3.0]));
+    }
+
+    #[test]
+    fn test_convert_polygon_to_geojson_feature_splits_at_antimeridian() {
+        let polygon = Polygon {
+            name: "barfoo".to_string(),
+            points: vec![vec![
+                Point { lat: -17.0, lon: 179.0 },
+                Point {
+                    lat: -17.0,
+                    lon: -179.0,
+                },
+                Point {
+                    lat: -16.0,
+                    lon: -179.0,
+                },
+                Point { lat: -16.0, lon: 179.0 },
+                Point { lat: -17.0, lon: 179.0 },
+            ]],
+            relation_id: 1,
+            admin_level: 1,
+            ..Default::default()
+        };
+
+        let unsplit = convert_polygon_to_geojson_feature(&polygon, &TagSelection::Nothing, false).unwrap();
+        let split = convert_polygon_to_geojson_feature(&polygon, &TagSelection::Nothing, true).unwrap();
+
+        assert!(matches!(unsplit.geometry.unwrap().value, Value::Polygon(_)));
+        match split.geometry.unwrap().value {
+            Value::MultiPolygon(polygons) => assert_eq!(polygons.len(), 2),
+            _ => panic!("expected a MultiPolygon"),
+        }
+    }
+
     #[test]
     fn test_create_properties_contains_name_of_polygon() {
         let poly_name = "barfoo";
@@ -213,8 +366,9 @@ mod tests {
             points: vec![vec![]],
             relation_id: 1,
             admin_level: 1,
+            ..Default::default()
         };
-        let result = create_properties(&single_polygon);
+        let result = create_properties(&single_polygon, &TagSelection::Nothing);
 
         assert!(result.contains_key("name"));
         assert_eq!(result.get("name").unwrap(), poly_name);
@@ -228,10 +382,151 @@ mod tests {
             points: vec![vec![]],
             relation_id: 1,
             admin_level: 27,
+            ..Default::default()
         };
-        let result = create_properties(&single_polygon);
+        let result = create_properties(&single_polygon, &TagSelection::Nothing);
 
         assert!(result.contains_key("admin_level"));
         assert_eq!(result.get("admin_level").unwrap(), 27);
     }
+
+    #[test]
+    fn test_create_properties_contains_parent_and_ancestors() {
+        let single_polygon = Polygon {
+            name: "barfoo".to_string(),
+            points: vec![vec![]],
+            relation_id: 3,
+            admin_level: 8,
+            ancestors: vec![
+                Ancestor {
+                    relation_id: 1,
+                    name: "foo".to_string(),
+                    admin_level: 2,
+                },
+                Ancestor {
+                    relation_id: 2,
+                    name: "bar".to_string(),
+                    admin_level: 4,
+                },
+            ],
+            ..Default::default()
+        };
+        let result = create_properties(&single_polygon, &TagSelection::Nothing);
+
+        assert_eq!(result.get("parent_relation_id").unwrap(), 2);
+        assert_eq!(result.get("ancestors").unwrap()[0]["name"], "foo");
+        assert_eq!(result.get("ancestors").unwrap()[1]["admin_level"], 4);
+    }
+
+    #[test]
+    fn test_create_properties_contains_metrics() {
+        let single_polygon = Polygon {
+            name: "barfoo".to_string(),
+            points: vec![vec![
+                Point { lat: 0.0, lon: 0.0 },
+                Point { lat: 0.0, lon: 2.0 },
+                Point { lat: 2.0, lon: 2.0 },
+                Point { lat: 2.0, lon: 0.0 },
+                Point { lat: 0.0, lon: 0.0 },
+            ]],
+            relation_id: 1,
+            admin_level: 1,
+            ..Default::default()
+        };
+        let result = create_properties(&single_polygon, &TagSelection::Nothing);
+
+        assert!(result.get("area_km2").unwrap().as_f64().unwrap() > 49000.0);
+        assert!(result.get("perimeter_km").unwrap().as_f64().unwrap() > 880.0);
+        assert_eq!(result.get("centroid").unwrap(), &json!([1.0, 1.0]));
+        assert!(result.get("pole_of_inaccessibility").unwrap().is_array());
+    }
+
+    #[test]
+    fn test_create_properties_contains_member_nodes() {
+        let single_polygon = Polygon {
+            name: "barfoo".to_string(),
+            points: vec![vec![]],
+            relation_id: 1,
+            admin_level: 1,
+            admin_centre: Some(PlaceNode {
+                node_id: 7,
+                lat: 49.0,
+                lon: 8.4,
+                name: Some("Karlsruhe".to_string()),
+                place: Some("city".to_string()),
+                population: None,
+            }),
+            ..Default::default()
+        };
+        let result = create_properties(&single_polygon, &TagSelection::Nothing);
+
+        assert_eq!(result.get("label_point").unwrap(), &serde_json::Value::Null);
+        assert_eq!(
+            result.get("admin_centre").unwrap(),
+            &json!({"node_id": 7, "coordinates": [8.4, 49.0], "name": "Karlsruhe", "place": "city", "population": null})
+        );
+
+        let points = render_points(&[single_polygon]);
+        assert_eq!(points.features.len(), 1);
+        assert_eq!(points.features[0].property("role").unwrap(), "admin_centre");
+        assert_eq!(points.features[0].property("boundary_name").unwrap(), "barfoo");
+    }
+
+    #[test]
+    fn test_create_properties_copies_selected_tags() {
+        let single_polygon = Polygon {
+            name: "barfoo".to_string(),
+            relation_id: 1,
+            admin_level: 8,
+            tags: [
+                ("name", "foo"),
+                ("name:en", "bar"),
+                ("name:de", "baz"),
+                ("wikidata", "Q1"),
+                ("population", "1000"),
+            ]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+            ..Default::default()
+        };
+        let selection = TagSelection::Patterns(vec![String::from("name:*"), String::from("wikidata")]);
+
+        let result = create_properties(&single_polygon, &selection);
+
+        assert_eq!(result.get("name").unwrap(), "barfoo");
+        assert_eq!(result.get("name:en").unwrap(), "bar");
+        assert_eq!(result.get("name:de").unwrap(), "baz");
+        assert_eq!(result.get("wikidata").unwrap(), "Q1");
+        assert!(!result.contains_key("population"));
+    }
+
+    #[test]
+    fn test_create_properties_does_not_copy_tags_by_default() {
+        let single_polygon = Polygon {
+            name: "barfoo".to_string(),
+            tags: vec![(String::from("wikidata"), String::from("Q1"))]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+
+        let result = create_properties(&single_polygon, &TagSelection::Nothing);
+
+        assert!(!result.contains_key("wikidata"));
+    }
+
+    #[test]
+    fn test_create_properties_has_null_parent_for_root() {
+        let single_polygon = Polygon {
+            name: "barfoo".to_string(),
+            points: vec![vec![]],
+            relation_id: 1,
+            admin_level: 2,
+            ..Default::default()
+        };
+        let result = create_properties(&single_polygon, &TagSelection::Nothing);
+
+        assert!(result.get("parent_relation_id").unwrap().is_null());
+    }
 }
@@ -0,0 +1,200 @@
+use crate::converter::{Point, Polygon};
+use crate::output::output_handler::FileWriter;
+
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::prelude::*;
+
+const SIZE: f64 = 512.0;
+const MARGIN: f64 = 8.0;
+const MAX_LATITUDE: f64 = 85.051_128_78;
+
+#[derive(Clone, Copy)]
+pub enum SvgProjection {
+    Equirectangular,
+    WebMercator,
+}
+
+pub struct SvgWriter {
+    pub projection: SvgProjection,
+}
+
+impl FileWriter for SvgWriter {
+    fn write_to_file(&self, file: &mut File, polygon: &Polygon) -> std::io::Result<()> {
+        file.write_all(render_svg(polygon, self.projection).as_bytes())
+    }
+}
+
+impl SvgProjection {
+    fn project(&self, point: &Point) -> (f64, f64) {
+        let lon = point.lon as f64;
+        let lat = point.lat as f64;
+        match self {
+            SvgProjection::Equirectangular => (lon, -lat),
+            SvgProjection::WebMercator => {
+                let lat = lat.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+                (lon, -(PI / 4.0 + lat / 2.0).tan().ln().to_degrees())
+            }
+        }
+    }
+}
+
+fn render_svg(polygon: &Polygon, projection: SvgProjection) -> String {
+    let rings: Vec<Vec<(f64, f64)>> = polygon
+        .points
+        .iter()
+        .map(|ring| ring.iter().map(|p| projection.project(p)).collect())
+        .collect();
+
+    let (min, max) = rings
+        .iter()
+        .flatten()
+        .fold(((f64::MAX, f64::MAX), (f64::MIN, f64::MIN)), |(min, max), &(x, y)| {
+            ((min.0.min(x), min.1.min(y)), (max.0.max(x), max.1.max(y)))
+        });
+
+    let extent = (max.0 - min.0).max(max.1 - min.1);
+    let scale = if extent > 0.0 {
+        (SIZE - 2.0 * MARGIN) / extent
+    } else {
+        1.0
+    };
+    let width = ((max.0 - min.0).max(0.0) * scale + 2.0 * MARGIN).ceil();
+    let height = ((max.1 - min.1).max(0.0) * scale + 2.0 * MARGIN).ceil();
+
+    let mut path = String::new();
+    for ring in rings.iter().filter(|r| !r.is_empty()) {
+        for (index, (x, y)) in ring.iter().enumerate() {
+            let command = if index == 0 { 'M' } else { 'L' };
+            path.push_str(&format!(
+                "{}{:.2} {:.2} ",
+                command,
+                (x - min.0) * scale + MARGIN,
+                (y - min.1) * scale + MARGIN
+            ));
+        }
+        path.push_str("Z ");
+    }
+
+    format!(
+        concat!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+            "<title>{title} ({id})</title>\n",
+            "<path d=\"{path}\" fill=\"#9ecae1\" fill-rule=\"evenodd\" stroke=\"#3182bd\" stroke-width=\"1\"/>\n",
+            "</svg>\n"
+        ),
+        w = width,
+        h = height,
+        title = escape(&polygon.name),
+        id = polygon.relation_id,
+        path = path.trim_end()
+    )
+}
+
+pub fn render_index(entries: &[(String, &Polygon)]) -> String {
+    let mut figures = String::new();
+    for (filename, polygon) in entries {
+        figures.push_str(&format!(
+            "<figure><img src=\"{src}\" loading=\"lazy\"><figcaption>{name}<br>relation {id}, admin level {level}</figcaption></figure>\n",
+            src = escape(&encode_uri_path(filename)),
+            name = escape(&polygon.name),
+            id = polygon.relation_id,
+            level = polygon.admin_level
+        ));
+    }
+
+    format!(
+        concat!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>osm_extract_polygon</title>\n",
+            "<style>body{{font-family:sans-serif}} figure{{display:inline-block;width:200px;margin:8px;vertical-align:top}} ",
+            "img{{width:200px;height:200px;object-fit:contain;border:1px solid #ccc}}</style>\n",
+            "</head>\n<body>\n{}</body>\n</html>\n"
+        ),
+        figures
+    )
+}
+
+fn escape(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn encode_uri_path(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'/' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_polygon(name: &str) -> Polygon {
+        let outer = vec![
+            Point { lat: 0.0, lon: 0.0 },
+            Point { lat: 0.0, lon: 10.0 },
+            Point { lat: 10.0, lon: 10.0 },
+            Point { lat: 10.0, lon: 0.0 },
+        ];
+        let inner = vec![
+            Point { lat: 2.0, lon: 2.0 },
+            Point { lat: 2.0, lon: 4.0 },
+            Point { lat: 4.0, lon: 4.0 },
+        ];
+        Polygon {
+            name: name.to_string(),
+            points: vec![outer, inner],
+            relation_id: 1,
+            admin_level: 8,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_render_svg_draws_every_ring_with_even_odd_fill() {
+        let result = render_svg(&create_polygon("barfoo"), SvgProjection::Equirectangular);
+
+        assert!(result.contains("fill-rule=\"evenodd\""));
+        assert_eq!(result.matches('M').count(), 2);
+        assert_eq!(result.matches('Z').count(), 2);
+    }
+
+    #[test]
+    fn test_render_svg_puts_north_on_top() {
+        let result = render_svg(&create_polygon("barfoo"), SvgProjection::Equirectangular);
+
+        // the first point of the outer ring lies on the southern edge and is drawn at the bottom
+        assert!(result.contains("M8.00 504.00"));
+    }
+
+    #[test]
+    fn test_web_mercator_stretches_high_latitudes() {
+        let equator = SvgProjection::WebMercator.project(&Point { lat: 1.0, lon: 0.0 });
+        let north = SvgProjection::WebMercator.project(&Point { lat: 60.0, lon: 0.0 }).1
+            - SvgProjection::WebMercator.project(&Point { lat: 59.0, lon: 0.0 }).1;
+
+        assert!(north.abs() > equator.1.abs());
+    }
+
+    #[test]
+    fn test_render_index_escapes_names() {
+        let polygon = create_polygon("A & B");
+        let result = render_index(&[(String::from("A & B.svg"), &polygon)]);
+
+        assert!(result.contains("A &amp; B<br>"));
+        assert!(result.contains("src=\"A%20%26%20B.svg\""));
+    }
+}
@@ -0,0 +1,121 @@
+use crate::converter::Polygon;
+
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "{name}";
+
+#[derive(Clone, Debug, PartialEq)]
+enum TemplatePart {
+    Literal(String),
+    Name,
+    RelationId,
+    AdminLevel,
+    Tag(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FilenameTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+impl Default for FilenameTemplate {
+    fn default() -> Self {
+        FilenameTemplate::parse(DEFAULT_FILENAME_TEMPLATE).unwrap()
+    }
+}
+
+impl FilenameTemplate {
+    pub fn parse(template: &str) -> Result<FilenameTemplate, String> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                parts.push(TemplatePart::Literal(rest[..start].to_string()));
+            }
+
+            let end = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => return Err(format!("unclosed placeholder in '{}'", template)),
+            };
+
+            let placeholder = &rest[start + 1..end];
+            parts.push(match placeholder {
+                "name" => TemplatePart::Name,
+                "relation_id" | "id" => TemplatePart::RelationId,
+                "admin_level" => TemplatePart::AdminLevel,
+                _ => match placeholder.strip_prefix("tag:") {
+                    Some(key) if !key.is_empty() => TemplatePart::Tag(key.to_string()),
+                    _ => return Err(format!("unknown placeholder {{{}}} in '{}'", placeholder, template)),
+                },
+            });
+
+            rest = &rest[end + 1..];
+        }
+
+        if !rest.is_empty() {
+            parts.push(TemplatePart::Literal(rest.to_string()));
+        }
+
+        Ok(FilenameTemplate { parts })
+    }
+
+    // path separators within the substituted values are removed, so only the template itself creates subdirectories
+    pub fn expand(&self, polygon: &Polygon) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                TemplatePart::Literal(text) => text.clone(),
+                TemplatePart::Name => without_separators(&polygon.name),
+                TemplatePart::RelationId => polygon.relation_id.to_string(),
+                TemplatePart::AdminLevel => polygon.admin_level.to_string(),
+                TemplatePart::Tag(key) => polygon.tags.get(key).map(|v| without_separators(v)).unwrap_or_default(),
+            })
+            .collect()
+    }
+}
+
+fn without_separators(value: &str) -> String {
+    value.chars().filter(|c| *c != '/' && *c != '\\').collect()
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon() -> Polygon {
+        let mut polygon = Polygon {
+            name: String::from("Baden/Württemberg"),
+            relation_id: 62611,
+            admin_level: 4,
+            ..Default::default()
+        };
+        polygon.tags.insert(String::from("ISO3166-2"), String::from("DE-BW"));
+        polygon
+    }
+
+    #[test]
+    fn test_expand_replaces_all_placeholders() {
+        let template = FilenameTemplate::parse("{admin_level}/{tag:ISO3166-2}_{name}_{relation_id}").unwrap();
+
+        assert_eq!(template.expand(&polygon()), "4/DE-BW_BadenWürttemberg_62611");
+    }
+
+    #[test]
+    fn test_expand_uses_empty_string_for_missing_tags() {
+        let template = FilenameTemplate::parse("{tag:ref}{name}").unwrap();
+
+        assert_eq!(template.expand(&polygon()), "BadenWürttemberg");
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_and_unclosed_placeholders() {
+        assert!(FilenameTemplate::parse("{population}").is_err());
+        assert!(FilenameTemplate::parse("{tag:}").is_err());
+        assert!(FilenameTemplate::parse("{name").is_err());
+    }
+}
@@ -0,0 +1,300 @@
+use crate::converter::{Point, Polygon};
+use crate::geometry::BoundingBox;
+use crate::metrics::Metrics;
+use crate::output::file_creator::FileCreator;
+use crate::output::file_writer_poly::render_poly;
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::prelude::*;
+use std::io::{ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+const CSV_HEADER: &str = concat!(
+    "relation_id,name,admin_level,status,reasons,files,min_lon,min_lat,max_lon,max_lat,rings,vertices,",
+    "area_km2,perimeter_km,centroid_lon,centroid_lat,pole_lon,pole_lat,content_hash"
+);
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+enum Status {
+    Ok,
+    Incomplete,
+    Skipped,
+    Failed,
+}
+
+impl Status {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Status::Ok => "ok",
+            Status::Incomplete => "incomplete",
+            Status::Skipped => "skipped",
+            Status::Failed => "failed",
+        }
+    }
+}
+
+struct Entry {
+    relation_id: i64,
+    name: String,
+    admin_level: i64,
+    status: Status,
+    reasons: Vec<String>,
+    files: Vec<String>,
+    bbox: Option<BoundingBox>,
+    rings: usize,
+    vertices: usize,
+    metrics: Metrics,
+    content_hash: String,
+}
+
+pub struct Manifest {
+    base_folder: PathBuf,
+    files: HashMap<i64, Vec<String>>,
+    problems: HashMap<i64, Vec<(Status, String)>>,
+}
+
+impl Manifest {
+    pub fn new(base_folder: &str) -> Manifest {
+        Manifest {
+            base_folder: PathBuf::from(base_folder),
+            files: HashMap::new(),
+            problems: HashMap::new(),
+        }
+    }
+
+    pub fn record<T>(&mut self, relation_id: i64, filename: &Path, result: &Result<T>) {
+        let relative = filename.strip_prefix(&self.base_folder).unwrap_or(filename);
+        let relative = relative.to_string_lossy().replace('\\', "/");
+
+        match result {
+            Ok(_) => self.files.entry(relation_id).or_default().push(relative),
+            Err(e) => {
+                let status = if e.kind() == ErrorKind::AlreadyExists {
+                    Status::Skipped
+                } else {
+                    Status::Failed
+                };
+                self.problems
+                    .entry(relation_id)
+                    .or_default()
+                    .push((status, format!("{}: {}", relative, e)));
+            }
+        }
+    }
+
+    pub fn write(&self, polygons: &[Polygon], file_creator: &mut FileCreator) -> Result<u64> {
+        let mut entries: Vec<Entry> = polygons.iter().map(|p| self.entry(p)).collect();
+        entries.sort_by_key(|e| e.relation_id);
+
+        let json_filename = self.base_folder.join("manifest.json");
+        file_creator
+            .create_file(&json_filename)?
+            .write_all(render_json(&entries).to_string().as_bytes())?;
+
+        let csv_filename = self.base_folder.join("manifest.csv");
+        file_creator
+            .create_file(&csv_filename)?
+            .write_all(render_csv(&entries).as_bytes())?;
+
+        Ok(2)
+    }
+
+    fn entry(&self, polygon: &Polygon) -> Entry {
+        let mut status = Status::Ok;
+        let mut reasons = incomplete_reasons(polygon);
+        if !reasons.is_empty() {
+            status = Status::Incomplete;
+        }
+
+        for (problem, reason) in self.problems.get(&polygon.relation_id).into_iter().flatten() {
+            if *problem > status {
+                status = *problem;
+            }
+            reasons.push(reason.clone());
+        }
+
+        Entry {
+            relation_id: polygon.relation_id,
+            name: polygon.name.clone(),
+            admin_level: polygon.admin_level,
+            status,
+            reasons,
+            files: self.files.get(&polygon.relation_id).cloned().unwrap_or_default(),
+            bbox: BoundingBox::of_polygon_wrapping(polygon),
+            rings: polygon.points.len(),
+            vertices: polygon.points.iter().map(|ring| ring.len()).sum(),
+            metrics: Metrics::of_polygon(polygon),
+            content_hash: content_hash(polygon),
+        }
+    }
+}
+
+fn incomplete_reasons(polygon: &Polygon) -> Vec<String> {
+    if polygon.points.is_empty() {
+        return vec![String::from("no ways found")];
+    }
+
+    polygon
+        .points
+        .iter()
+        .enumerate()
+        .filter(|(_, ring)| ring.len() < 4 || !is_closed(ring))
+        .map(|(index, _)| format!("area_{} is not closed", index + 1))
+        .collect()
+}
+
+fn is_closed(ring: &[Point]) -> bool {
+    match (ring.first(), ring.last()) {
+        (Some(first), Some(last)) => first.lat == last.lat && first.lon == last.lon,
+        _ => false,
+    }
+}
+
+// the hash of the .poly representation, i.e., it matches the checksum of the written .poly file
+pub fn content_hash(polygon: &Polygon) -> String {
+    Sha256::digest(render_poly(polygon).as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn render_json(entries: &[Entry]) -> Value {
+    let boundaries: Vec<Value> = entries
+        .iter()
+        .map(|e| {
+            json!({
+                "relation_id": e.relation_id,
+                "name": e.name,
+                "admin_level": e.admin_level,
+                "status": e.status.as_str(),
+                "reasons": e.reasons,
+                "files": e.files,
+                "bbox": e.bbox.map(|b| vec![b.min_lon, b.min_lat, b.max_lon, b.max_lat]),
+                "rings": e.rings,
+                "vertices": e.vertices,
+                "area_km2": e.metrics.area_km2,
+                "perimeter_km": e.metrics.perimeter_km,
+                "centroid": e.metrics.centroid.map(|(lon, lat)| vec![lon, lat]),
+                "pole_of_inaccessibility": e.metrics.pole_of_inaccessibility.map(|(lon, lat)| vec![lon, lat]),
+                "content_hash": e.content_hash,
+            })
+        })
+        .collect();
+
+    json!({ "boundaries": boundaries })
+}
+
+fn render_csv(entries: &[Entry]) -> String {
+    let mut output = String::from(CSV_HEADER);
+    output.push('\n');
+
+    for e in entries {
+        let bbox = match e.bbox {
+            Some(b) => format!("{},{},{},{}", b.min_lon, b.min_lat, b.max_lon, b.max_lat),
+            None => String::from(",,,"),
+        };
+        let point = |p: Option<(f64, f64)>| match p {
+            Some((lon, lat)) => format!("{},{}", lon, lat),
+            None => String::from(","),
+        };
+        output.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+            e.relation_id,
+            escape_csv(&e.name),
+            e.admin_level,
+            e.status.as_str(),
+            escape_csv(&e.reasons.join(";")),
+            escape_csv(&e.files.join(";")),
+            bbox,
+            e.rings,
+            e.vertices,
+            e.metrics.area_km2,
+            e.metrics.perimeter_km,
+            point(e.metrics.centroid),
+            point(e.metrics.pole_of_inaccessibility),
+            e.content_hash
+        ));
+    }
+
+    output
+}
+
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::square_ring;
+    use std::io::Error;
+
+    fn square() -> Polygon {
+        Polygon {
+            name: String::from("Square, \"quoted\""),
+            points: vec![square_ring(0.0, 1.0)],
+            relation_id: 42,
+            admin_level: 8,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_entry_lists_files_and_problems_relative_to_base_folder() {
+        let mut manifest = Manifest::new("out");
+        manifest.record(42, Path::new("out/8/Square.poly"), &Ok(()));
+        manifest.record::<()>(
+            42,
+            Path::new("out/8/Square.geojson"),
+            &Err(Error::new(ErrorKind::AlreadyExists, "skipped")),
+        );
+
+        let entry = manifest.entry(&square());
+
+        assert_eq!(entry.status, Status::Skipped);
+        assert_eq!(entry.files, ["8/Square.poly"]);
+        assert_eq!(entry.reasons, ["8/Square.geojson: skipped"]);
+        assert_eq!((entry.rings, entry.vertices), (1, 5));
+        assert!((entry.metrics.area_km2 - 12308.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn test_entry_marks_open_and_empty_boundaries_as_incomplete() {
+        let manifest = Manifest::new("out");
+        let mut open = square();
+        open.points[0].pop();
+
+        let entry = manifest.entry(&open);
+        assert_eq!(entry.status, Status::Incomplete);
+        assert_eq!(entry.reasons, ["area_1 is not closed"]);
+
+        let entry = manifest.entry(&Polygon::default());
+        assert_eq!(entry.status, Status::Incomplete);
+        assert_eq!(entry.reasons, ["no ways found"]);
+        assert!(entry.bbox.is_none());
+    }
+
+    #[test]
+    fn test_render_csv_escapes_fields() {
+        let manifest = Manifest::new("out");
+        let entry = manifest.entry(&square());
+
+        let csv = render_csv(&[entry]);
+        let row = csv.lines().nth(1).unwrap();
+
+        assert!(row.starts_with("42,\"Square, \"\"quoted\"\"\",8,ok,,,0,0,1,1,1,5,"));
+        assert_eq!(row.rsplit(',').next().unwrap(), content_hash(&square()));
+    }
+}
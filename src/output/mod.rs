@@ -1,11 +1,50 @@
+use crate::output::filename_template::FilenameTemplate;
+use crate::utils::matches_glob;
+
 pub mod output_handler;
 
-mod file_creator;
+pub mod file_creator;
+mod file_writer_bbox;
 mod file_writer_geojson;
-mod file_writer_poly;
+pub mod file_writer_poly;
+pub mod file_writer_svg;
+pub mod filename_template;
+pub mod manifest;
 
+#[derive(Clone, Copy)]
 pub enum OverwriteConfiguration {
     Ask,
     OverwriteAll,
     SkipAll,
 }
+
+#[derive(Clone, Copy, Default)]
+pub enum OutputLayout {
+    #[default]
+    Flat,
+    Hierarchical,
+}
+
+#[derive(Clone, Default)]
+pub struct FilenameConfiguration {
+    pub template: FilenameTemplate,
+    pub layout: OutputLayout,
+    pub portable: bool,
+}
+
+#[derive(Clone)]
+pub enum TagSelection {
+    Nothing,
+    All,
+    Patterns(Vec<String>),
+}
+
+impl TagSelection {
+    pub fn is_selected(&self, key: &str) -> bool {
+        match self {
+            TagSelection::Nothing => false,
+            TagSelection::All => true,
+            TagSelection::Patterns(patterns) => patterns.iter().any(|p| matches_glob(p, key)),
+        }
+    }
+}
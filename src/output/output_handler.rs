@@ -1,15 +1,28 @@
 use crate::converter::Polygon;
+use crate::hierarchy::render_tree;
 use crate::output::file_creator::FileCreator;
-use crate::output::file_writer_geojson::GeoJsonWriter;
+use crate::output::file_writer_bbox::BboxWriter;
+use crate::output::file_writer_geojson::{render_points, GeoJsonWriter};
 use crate::output::file_writer_poly::PolyWriter;
-use crate::output::OverwriteConfiguration;
+use crate::output::file_writer_svg::{render_index, SvgProjection, SvgWriter};
+use crate::output::manifest::Manifest;
+use crate::output::{FilenameConfiguration, OutputLayout, OverwriteConfiguration, TagSelection};
 
-use std::collections::HashSet;
+use deunicode::deunicode_with_tofu;
+use std::collections::{HashMap, HashSet};
 use std::fs::{create_dir_all, File};
+use std::io::prelude::*;
 use std::io::Result;
 use std::path::{Path, PathBuf};
 use std::time::Instant;
 
+const NO_PARENT_FOLDER: &str = "_no_parent";
+const MAX_PORTABLE_NAME_BYTES: usize = 200;
+const RESERVED_WINDOWS_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1", "LPT2",
+    "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
 pub trait FileWriter {
     fn write_to_file(&self, file: &mut File, polygon: &Polygon) -> Result<()>;
 }
@@ -17,60 +30,130 @@ pub trait FileWriter {
 pub struct OutputHandlerConfiguration {
     pub overwrite_configuration: OverwriteConfiguration,
     pub geojson_output: bool,
+    pub geojson_tags: TagSelection,
+    pub split_antimeridian: bool,
+    pub bbox_output: bool,
+    pub svg_output: Option<SvgProjection>,
+    pub svg_index: bool,
+    pub hierarchy_output: bool,
+    pub points_output: bool,
+    pub filename_configuration: FilenameConfiguration,
+    // if set, existing files of all other boundaries are kept as they are (the summary files are always written)
+    pub only_relation_ids: Option<HashSet<i64>>,
 }
 
-pub fn write(folder: &str, polygons: &[Polygon], config: OutputHandlerConfiguration) -> Result<u64> {
+pub fn write(
+    folder: &str,
+    polygons: &[Polygon],
+    config: OutputHandlerConfiguration,
+    manifest: &mut Manifest,
+) -> Result<u64> {
     create_dir_all(folder)?;
 
-    let filename_polys = pair_safe_filenames_and_polygons(polygons);
-    let mut output_handler = new_output_handler(config);
+    let filename_polys = pair_safe_filenames_and_polygons(polygons, &config.filename_configuration);
+    let mut output_handler = new_output_handler(config, manifest);
 
-    output_handler.write_files(folder, filename_polys)
+    output_handler.write_files(folder, polygons, filename_polys)
 }
 
-fn new_output_handler(config: OutputHandlerConfiguration) -> OutputHandler {
+fn new_output_handler(config: OutputHandlerConfiguration, manifest: &mut Manifest) -> OutputHandler<'_> {
     OutputHandler {
         file_creator: FileCreator {
             overwrite_mode_config: config.overwrite_configuration,
         },
         write_poly: true,
         write_geojson: config.geojson_output,
+        geojson_tags: config.geojson_tags,
+        split_antimeridian: config.split_antimeridian,
+        write_bbox: config.bbox_output,
+        write_svg: config.svg_output,
+        write_svg_index: config.svg_index,
+        write_hierarchy: config.hierarchy_output,
+        write_points: config.points_output,
+        only_relation_ids: config.only_relation_ids,
+        manifest,
     }
 }
 
-struct OutputHandler {
+struct OutputHandler<'a> {
     file_creator: FileCreator,
     write_poly: bool,
     write_geojson: bool,
+    geojson_tags: TagSelection,
+    split_antimeridian: bool,
+    write_bbox: bool,
+    write_svg: Option<SvgProjection>,
+    write_svg_index: bool,
+    write_hierarchy: bool,
+    write_points: bool,
+    only_relation_ids: Option<HashSet<i64>>,
+    manifest: &'a mut Manifest,
 }
 
-impl OutputHandler {
-    pub fn write_files(&mut self, base_folder: &str, filename_polys: Vec<(String, &Polygon)>) -> Result<u64> {
+impl OutputHandler<'_> {
+    pub fn write_files(
+        &mut self,
+        base_folder: &str,
+        polygons: &[Polygon],
+        filename_polys: Vec<(String, &Polygon)>,
+    ) -> Result<u64> {
         let mut file_count = 0;
 
         let poly_writer = PolyWriter {};
-        let geojson_writer = GeoJsonWriter {};
+        let geojson_writer = GeoJsonWriter {
+            tag_selection: self.geojson_tags.clone(),
+            split_antimeridian: self.split_antimeridian,
+        };
 
         let now = Instant::now();
         println!("writing output files...");
 
+        let mut svg_files: Vec<(String, &Polygon)> = Vec::new();
+
         for (name, polygon) in filename_polys {
-            let filename_wo_ext: PathBuf = [base_folder.to_string(), name].iter().collect();
-            if self.write_poly
-                && self.write_file(filename_wo_ext.with_extension("poly").as_path(), polygon, &poly_writer)
-            {
+            let with_extension = |extension: &str| -> PathBuf {
+                [base_folder.to_string(), format!("{}.{}", name, extension)]
+                    .iter()
+                    .collect()
+            };
+
+            if self.write_poly && self.write_file(&with_extension("poly"), polygon, &poly_writer) {
                 file_count += 1;
             }
 
-            if self.write_geojson
-                && self.write_file(
-                    filename_wo_ext.with_extension("geojson").as_path(),
-                    polygon,
-                    &geojson_writer,
-                )
-            {
+            if self.write_geojson && self.write_file(&with_extension("geojson"), polygon, &geojson_writer) {
                 file_count += 1;
             }
+
+            if self.write_bbox && self.write_file(&with_extension("bbox"), polygon, &BboxWriter {}) {
+                file_count += 1;
+            }
+
+            if let Some(projection) = self.write_svg {
+                let svg_writer = SvgWriter { projection };
+                let svg_filename = with_extension("svg");
+                // the index links existing files that were kept or skipped, but not files that failed to be written
+                let existed = svg_filename.is_file();
+                let written = self.write_file(&svg_filename, polygon, &svg_writer);
+                if written {
+                    file_count += 1;
+                }
+                if written || existed {
+                    svg_files.push((format!("{}.svg", name), polygon));
+                }
+            }
+        }
+
+        if self.write_svg_index && self.write_index(base_folder, &svg_files) {
+            file_count += 1;
+        }
+
+        if self.write_hierarchy && self.write_hierarchy_file(base_folder, polygons) {
+            file_count += 1;
+        }
+
+        if self.write_points && self.write_points_file(base_folder, polygons) {
+            file_count += 1;
         }
 
         println!("finished writing! {}s", now.elapsed().as_secs());
@@ -78,56 +161,221 @@ impl OutputHandler {
     }
 
     pub fn write_file(&mut self, filename_wo_ext: &Path, polygon: &Polygon, file_writer: &impl FileWriter) -> bool {
+        if is_kept(&self.only_relation_ids, filename_wo_ext, polygon) {
+            self.manifest.record(polygon.relation_id, filename_wo_ext, &Ok(()));
+            return false;
+        }
+
         let result = self
             .file_creator
             .create_file(filename_wo_ext)
             .and_then(|mut file| file_writer.write_to_file(&mut file, polygon));
 
-        let filename_str = filename_wo_ext.as_os_str().to_str().unwrap();
+        self.manifest.record(polygon.relation_id, filename_wo_ext, &result);
+        report_result(filename_wo_ext, result)
+    }
 
-        match result {
-            Err(e) => {
-                println!("{}: {}", filename_str, e);
-                false
-            }
-            Ok(_) => {
-                println!("{}: successfully written ", filename_str);
-                true
-            }
+    fn write_index(&mut self, base_folder: &str, svg_files: &[(String, &Polygon)]) -> bool {
+        let filename: PathBuf = [base_folder, "index.html"].iter().collect();
+        let result = self
+            .file_creator
+            .create_file(&filename)
+            .and_then(|mut file| file.write_all(render_index(svg_files).as_bytes()));
+
+        report_result(&filename, result)
+    }
+
+    fn write_points_file(&mut self, base_folder: &str, polygons: &[Polygon]) -> bool {
+        let filename: PathBuf = [base_folder, "points.geojson"].iter().collect();
+        let result = self
+            .file_creator
+            .create_file(&filename)
+            .and_then(|mut file| file.write_all(render_points(polygons).to_string().as_bytes()));
+
+        report_result(&filename, result)
+    }
+
+    fn write_hierarchy_file(&mut self, base_folder: &str, polygons: &[Polygon]) -> bool {
+        let filename: PathBuf = [base_folder, "hierarchy.json"].iter().collect();
+        let result = self
+            .file_creator
+            .create_file(&filename)
+            .and_then(|mut file| file.write_all(render_tree(polygons).to_string().as_bytes()));
+
+        report_result(&filename, result)
+    }
+}
+
+pub fn is_kept(only_relation_ids: &Option<HashSet<i64>>, filename: &Path, polygon: &Polygon) -> bool {
+    only_relation_ids
+        .as_ref()
+        .is_some_and(|ids| !ids.contains(&polygon.relation_id))
+        && filename.exists()
+}
+
+fn report_result(filename: &Path, result: Result<()>) -> bool {
+    let filename_str = filename.as_os_str().to_str().unwrap();
+
+    match result {
+        Err(e) => {
+            println!("{}: {}", filename_str, e);
+            false
+        }
+        Ok(_) => {
+            println!("{}: successfully written ", filename_str);
+            true
         }
     }
 }
 
-fn pair_safe_filenames_and_polygons(polygons: &[Polygon]) -> Vec<(String, &Polygon)> {
-    let safe_names: Vec<String> = polygons.iter().map(|p| make_safe(&p.name)).collect();
+pub fn paths_with_extension<'a>(
+    folder: &str,
+    polygons: &'a [Polygon],
+    filename_configuration: &FilenameConfiguration,
+    extension: &str,
+) -> Vec<(PathBuf, &'a Polygon)> {
+    pair_safe_filenames_and_polygons(polygons, filename_configuration)
+        .into_iter()
+        .map(|(name, polygon)| {
+            let path: PathBuf = [folder.to_string(), format!("{}.{}", name, extension)].iter().collect();
+            (path, polygon)
+        })
+        .collect()
+}
+
+fn pair_safe_filenames_and_polygons<'a>(
+    polygons: &'a [Polygon],
+    filename_configuration: &FilenameConfiguration,
+) -> Vec<(String, &'a Polygon)> {
+    let safe_names: Vec<String> = polygons
+        .iter()
+        .map(|p| {
+            make_safe_path(
+                &filename_configuration.template.expand(p),
+                p.relation_id,
+                filename_configuration.portable,
+            )
+        })
+        .collect();
+
+    let out_names = match filename_configuration.layout {
+        OutputLayout::Flat => resolve_duplicates(
+            safe_names
+                .into_iter()
+                .zip(polygons.iter().map(|p| p.relation_id))
+                .collect(),
+        ),
+        OutputLayout::Hierarchical => nest_by_ancestors(polygons, safe_names),
+    };
+
+    polygons.iter().zip(out_names).map(|(p, name)| (name, p)).collect()
+}
+
+fn resolve_duplicates(names_and_ids: Vec<(String, i64)>) -> Vec<String> {
     let mut seen_names: HashSet<String> = HashSet::new();
     let mut duplicate_names: HashSet<String> = HashSet::new();
 
-    for name in &safe_names {
+    for (name, _) in &names_and_ids {
         let lower_name = name.to_lowercase();
         if !seen_names.insert(lower_name.clone()) {
             duplicate_names.insert(lower_name);
         }
     }
 
-    polygons
-        .iter()
-        .zip(safe_names)
-        .map(|(p, name)| {
-            let out_name = if duplicate_names.contains(&name.to_lowercase()) {
-                format!("{}_{}", name, p.relation_id)
+    names_and_ids
+        .into_iter()
+        .map(|(name, relation_id)| {
+            if duplicate_names.contains(&name.to_lowercase()) {
+                format!("{}_{}", name, relation_id)
             } else {
                 name
-            };
-            (out_name, p)
+            }
         })
         .collect()
 }
 
+// every boundary is placed in the folder named after the (final) filename of its parent; boundaries on the lowest
+// admin level form the top level, all others without an extracted parent are collected in NO_PARENT_FOLDER
+fn nest_by_ancestors(polygons: &[Polygon], safe_names: Vec<String>) -> Vec<String> {
+    let index_of: HashMap<i64, usize> = polygons.iter().enumerate().map(|(i, p)| (p.relation_id, i)).collect();
+    let min_admin_level = polygons.iter().map(|p| p.admin_level).min().unwrap_or_default();
+    let max_depth = polygons.iter().map(|p| p.ancestors.len()).max().unwrap_or_default();
+
+    let mut out_names: Vec<String> = vec![String::new(); polygons.len()];
+
+    // a parent always has one ancestor less than its child, hence its name is final once the child's depth is reached
+    for depth in 0..=max_depth {
+        let members: Vec<usize> = (0..polygons.len())
+            .filter(|&i| polygons[i].ancestors.len() == depth)
+            .collect();
+
+        let nested_names: Vec<(String, i64)> = members
+            .iter()
+            .map(|&i| {
+                let polygon = &polygons[i];
+                let name = match polygon.parent_relation_id().and_then(|id| index_of.get(&id)) {
+                    Some(&parent) => format!("{}/{}", out_names[parent], safe_names[i]),
+                    None if polygon.admin_level > min_admin_level => format!("{}/{}", NO_PARENT_FOLDER, safe_names[i]),
+                    None => safe_names[i].clone(),
+                };
+                (name, polygon.relation_id)
+            })
+            .collect();
+
+        for (&i, name) in members.iter().zip(resolve_duplicates(nested_names)) {
+            out_names[i] = name;
+        }
+    }
+
+    out_names
+}
+
+// sanitises every directory level of an expanded template; levels that end up empty are dropped
+fn make_safe_path(path: &str, relation_id: i64, portable: bool) -> String {
+    let segments: Vec<String> = path
+        .split('/')
+        .map(|segment| {
+            if portable {
+                make_portable(segment)
+            } else {
+                make_safe(segment)
+            }
+        })
+        .filter(|segment| !segment.is_empty() && segment != "." && segment != "..")
+        .collect();
+
+    if segments.is_empty() {
+        relation_id.to_string()
+    } else {
+        segments.join("/")
+    }
+}
+
 fn make_safe(name: &str) -> String {
     name.chars().filter(|c| !r"\\/&:<>|*".contains(*c)).collect()
 }
 
+// ascii only, without characters or names that are forbidden on windows, and short enough for every file system
+fn make_portable(name: &str) -> String {
+    let ascii: String = make_safe(&deunicode_with_tofu(name, "_"))
+        .chars()
+        .filter(|c| !c.is_ascii_control() && *c != '?' && *c != '"')
+        .take(MAX_PORTABLE_NAME_BYTES)
+        .collect();
+
+    let trimmed = ascii.trim_start().trim_end_matches(['.', ' ']);
+    let stem = trimmed.split('.').next().unwrap_or_default().trim_end();
+
+    if RESERVED_WINDOWS_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        format!("_{}", trimmed)
+    } else {
+        trimmed.to_string()
+    }
+}
+
 // ////////////////////////////////////
 // ////////////////////////////////////
 // UNIT TESTS
@@ -137,6 +385,8 @@ fn make_safe(name: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::converter::Ancestor;
+    use crate::output::filename_template::FilenameTemplate;
 
     #[test]
     fn test_make_safe_remvoes_forbidden_chars() {
@@ -158,6 +408,30 @@ mod tests {
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn test_make_portable_transliterates_to_ascii() {
+        assert_eq!(make_portable("Łódź"), "Lodz");
+        assert_eq!(make_portable("Москва"), "Moskva");
+        assert_eq!(make_portable("Baden-Württemberg"), "Baden-Wurttemberg");
+    }
+
+    #[test]
+    fn test_make_portable_removes_characters_forbidden_on_windows() {
+        assert_eq!(make_portable("What? \"Name\"\t..."), "What Name");
+        assert_eq!(make_portable("CON"), "_CON");
+        assert_eq!(make_portable("lpt1.old"), "_lpt1.old");
+        assert_eq!(make_portable("Conway"), "Conway");
+    }
+
+    #[test]
+    fn test_make_portable_limits_length() {
+        let input = "Ä".repeat(300);
+
+        let result = make_portable(&input);
+
+        assert_eq!(result.len(), MAX_PORTABLE_NAME_BYTES);
+    }
+
     #[test]
     fn test_create_filenames_add_extensions_to_duplicate_regions() {
         let p1_name = String::from("spain_region");
@@ -178,6 +452,7 @@ mod tests {
             points: Vec::new(),
             relation_id: 100,
             admin_level: 1,
+            ..Default::default()
         };
 
         let p2 = Polygon {
@@ -185,6 +460,7 @@ mod tests {
             points: Vec::new(),
             relation_id: 200,
             admin_level: 1,
+            ..Default::default()
         };
 
         let p3 = Polygon {
@@ -192,6 +468,7 @@ mod tests {
             points: Vec::new(),
             relation_id: 300,
             admin_level: 1,
+            ..Default::default()
         };
 
         let p4 = Polygon {
@@ -199,11 +476,12 @@ mod tests {
             points: Vec::new(),
             relation_id: 400,
             admin_level: 1,
+            ..Default::default()
         };
 
         let input = [p1, p2, p3, p4];
 
-        let result = pair_safe_filenames_and_polygons(&input);
+        let result = pair_safe_filenames_and_polygons(&input, &FilenameConfiguration::default());
 
         let result_names: Vec<String> = result.iter().map(|(x, _y)| x).cloned().collect();
 
@@ -223,6 +501,7 @@ mod tests {
             points: Vec::new(),
             relation_id: 1,
             admin_level: 1,
+            ..Default::default()
         };
 
         let p2 = Polygon {
@@ -230,6 +509,7 @@ mod tests {
             points: Vec::new(),
             relation_id: 2,
             admin_level: 1,
+            ..Default::default()
         };
 
         let p3 = Polygon {
@@ -237,11 +517,12 @@ mod tests {
             points: Vec::new(),
             relation_id: 3,
             admin_level: 1,
+            ..Default::default()
         };
 
         let input = [p1, p2, p3];
 
-        let result = pair_safe_filenames_and_polygons(&input);
+        let result = pair_safe_filenames_and_polygons(&input, &FilenameConfiguration::default());
 
         let result_names: Vec<String> = result.iter().map(|(x, _y)| x).cloned().collect();
 
@@ -260,6 +541,7 @@ mod tests {
             points: Vec::new(),
             relation_id: 123,
             admin_level: 1,
+            ..Default::default()
         };
 
         let p2 = Polygon {
@@ -267,14 +549,181 @@ mod tests {
             points: Vec::new(),
             relation_id: 456,
             admin_level: 1,
+            ..Default::default()
         };
 
         let input = [p1, p2];
 
-        let result = pair_safe_filenames_and_polygons(&input);
+        let result = pair_safe_filenames_and_polygons(&input, &FilenameConfiguration::default());
 
         let result_names: Vec<String> = result.iter().map(|(x, _y)| x).cloned().collect();
 
         assert_eq!(result_names, expected);
     }
+
+    #[test]
+    fn test_create_filenames_applies_template_and_sanitises_each_directory() {
+        let template = FilenameTemplate::parse("{admin_level}/../{name}:_{relation_id}").unwrap();
+
+        let p1 = Polygon {
+            name: String::from("Karlsruhe"),
+            relation_id: 62518,
+            admin_level: 6,
+            ..Default::default()
+        };
+
+        let input = [p1];
+
+        let result = pair_safe_filenames_and_polygons(
+            &input,
+            &FilenameConfiguration {
+                template,
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(result[0].0, "6/Karlsruhe_62518");
+    }
+
+    #[test]
+    fn test_create_filenames_resolves_collisions_of_expanded_template() {
+        let template = FilenameTemplate::parse("{admin_level}/{name}").unwrap();
+
+        let p1 = Polygon {
+            name: String::from("Twin"),
+            relation_id: 5,
+            admin_level: 8,
+            ..Default::default()
+        };
+
+        let p2 = Polygon {
+            name: String::from("twin"),
+            relation_id: 6,
+            admin_level: 8,
+            ..Default::default()
+        };
+
+        let p3 = Polygon {
+            name: String::from("Twin"),
+            relation_id: 7,
+            admin_level: 6,
+            ..Default::default()
+        };
+
+        let input = [p1, p2, p3];
+
+        let result = pair_safe_filenames_and_polygons(
+            &input,
+            &FilenameConfiguration {
+                template,
+                ..Default::default()
+            },
+        );
+
+        let result_names: Vec<String> = result.iter().map(|(x, _y)| x).cloned().collect();
+
+        assert_eq!(result_names, ["8/Twin_5", "8/twin_6", "6/Twin"]);
+    }
+
+    fn ancestor(relation_id: i64, admin_level: i64) -> Ancestor {
+        Ancestor {
+            relation_id,
+            name: String::new(),
+            admin_level,
+        }
+    }
+
+    #[test]
+    fn test_create_filenames_nests_boundaries_in_folders_of_their_parents() {
+        let country = Polygon {
+            name: String::from("Germany"),
+            relation_id: 1,
+            admin_level: 2,
+            ..Default::default()
+        };
+
+        let state = Polygon {
+            name: String::from("Baden-Württemberg"),
+            relation_id: 2,
+            admin_level: 4,
+            ancestors: vec![ancestor(1, 2)],
+            ..Default::default()
+        };
+
+        let city = Polygon {
+            name: String::from("Karlsruhe"),
+            relation_id: 3,
+            admin_level: 6,
+            ancestors: vec![ancestor(1, 2), ancestor(2, 4)],
+            ..Default::default()
+        };
+
+        let orphan = Polygon {
+            name: String::from("Karlsruhe"),
+            relation_id: 4,
+            admin_level: 8,
+            ..Default::default()
+        };
+
+        let input = [city, orphan, state, country];
+
+        let result = pair_safe_filenames_and_polygons(
+            &input,
+            &FilenameConfiguration {
+                layout: OutputLayout::Hierarchical,
+                ..Default::default()
+            },
+        );
+
+        let result_names: Vec<String> = result.iter().map(|(x, _y)| x).cloned().collect();
+
+        assert_eq!(
+            result_names,
+            [
+                "Germany/Baden-Württemberg/Karlsruhe",
+                "_no_parent/Karlsruhe",
+                "Germany/Baden-Württemberg",
+                "Germany",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_create_filenames_nested_duplicates_use_final_parent_folder() {
+        let country = Polygon {
+            name: String::from("Twin"),
+            relation_id: 1,
+            admin_level: 2,
+            ..Default::default()
+        };
+
+        let other_country = Polygon {
+            name: String::from("Twin"),
+            relation_id: 2,
+            admin_level: 2,
+            ..Default::default()
+        };
+
+        let child = Polygon {
+            name: String::from("Town"),
+            relation_id: 3,
+            admin_level: 8,
+            ancestors: vec![ancestor(2, 2)],
+            ..Default::default()
+        };
+
+        let input = [country, other_country, child];
+
+        let result = pair_safe_filenames_and_polygons(
+            &input,
+            &FilenameConfiguration {
+                layout: OutputLayout::Hierarchical,
+                ..Default::default()
+            },
+        );
+
+        let result_names: Vec<String> = result.iter().map(|(x, _y)| x).cloned().collect();
+
+        assert_eq!(result_names, ["Twin_1", "Twin_2", "Twin_2/Town"]);
+    }
 }
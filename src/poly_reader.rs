@@ -0,0 +1,271 @@
+use crate::converter::{Point, Polygon};
+use crate::geometry;
+
+use geo::BooleanOps;
+use geo_types::MultiPolygon;
+
+use std::fmt;
+use std::fs::read_to_string;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolySection {
+    pub name: String,
+    // sections whose name starts with '!' are cut out of the polygon
+    pub is_hole: bool,
+    pub line: usize,
+    pub points: Vec<Point>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolyFile {
+    pub name: String,
+    pub sections: Vec<PolySection>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PolyError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for PolyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl PolyFile {
+    // every section becomes a ring, which holes they form is derived from their nesting like for boundaries
+    pub fn to_polygon(&self) -> Polygon {
+        Polygon {
+            name: self.name.clone(),
+            points: self.sections.iter().map(|section| section.points.clone()).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn to_multi_polygon(&self) -> MultiPolygon<f64> {
+        /*
+            the sections without '!' are nested like the rings of a boundary, since the .poly files written
+            by this tool do not mark holes; the '!' sections are then subtracted from the result
+        */
+        if self.sections.iter().all(|section| !section.is_hole) {
+            return geometry::to_multi_polygon(&self.to_polygon());
+        }
+
+        let rings = |is_hole: bool| Polygon {
+            points: self
+                .sections
+                .iter()
+                .filter(|section| section.is_hole == is_hole)
+                .map(|section| section.points.clone())
+                .collect(),
+            ..Default::default()
+        };
+
+        geometry::to_multi_polygon(&rings(false)).difference(&geometry::to_multi_polygon(&rings(true)))
+    }
+}
+
+pub fn read_poly(filename: &str) -> Result<PolyFile, String> {
+    let text = read_to_string(filename).map_err(|e| format!("{}: {}", filename, e))?;
+    parse_poly(&text).map_err(|e| format!("{}: {}", filename, e))
+}
+
+enum State {
+    Name,
+    BetweenSections,
+    InSection(PolySection),
+    Finished,
+}
+
+pub fn parse_poly(text: &str) -> Result<PolyFile, PolyError> {
+    /*
+        Osmosis polygon format: the first line is the name, followed by sections that start with
+        a name line, contain one 'lon lat' pair per line, and end with END; a final END closes the file
+    */
+    let mut name = String::new();
+    let mut sections = Vec::new();
+    let mut state = State::Name;
+    let mut last_line = 0;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.trim();
+        last_line = line_number;
+        if line.is_empty() {
+            continue;
+        }
+
+        let error = |message: String| {
+            Err(PolyError {
+                line: line_number,
+                message,
+            })
+        };
+
+        state = match state {
+            State::Name => {
+                name = line.to_string();
+                State::BetweenSections
+            }
+            State::BetweenSections if line == "END" => State::Finished,
+            State::BetweenSections => {
+                if parse_point(line).is_ok() {
+                    return error(format!("expected a section name or END, found coordinates '{}'", line));
+                }
+                let (section_name, is_hole) = match line.strip_prefix('!') {
+                    Some(section_name) => (section_name.to_string(), true),
+                    None => (line.to_string(), false),
+                };
+                State::InSection(PolySection {
+                    name: section_name,
+                    is_hole,
+                    line: line_number,
+                    points: Vec::new(),
+                })
+            }
+            State::InSection(section) if line == "END" => {
+                if section.points.is_empty() {
+                    return error(format!("section '{}' has no coordinates", section.name));
+                }
+                sections.push(section);
+                State::BetweenSections
+            }
+            State::InSection(mut section) => {
+                match parse_point(line) {
+                    Ok(point) => section.points.push(point),
+                    Err(message) => return error(message),
+                }
+                State::InSection(section)
+            }
+            State::Finished => return error(format!("unexpected content after the final END: '{}'", line)),
+        };
+    }
+
+    let error = |message: String| {
+        Err(PolyError {
+            line: last_line.max(1),
+            message,
+        })
+    };
+    match state {
+        State::Finished => Ok(PolyFile { name, sections }),
+        State::Name => error(String::from("file is empty")),
+        State::BetweenSections => error(String::from("missing the final END")),
+        State::InSection(section) => error(format!(
+            "section '{}' starting at line {} is missing its END",
+            section.name, section.line
+        )),
+    }
+}
+
+fn parse_point(line: &str) -> Result<Point, String> {
+    let values: Vec<f64> = line
+        .split_whitespace()
+        .map(|value| value.parse::<f64>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("invalid coordinate '{}'", line))?;
+
+    match values[..] {
+        [lon, lat] if (-180.0..=180.0).contains(&lon) && (-90.0..=90.0).contains(&lat) => Ok(Point {
+            lat: lat as f32,
+            lon: lon as f32,
+        }),
+        [_, _] => Err(format!("coordinate '{}' is out of range", line)),
+        _ => Err(format!("invalid coordinate '{}', expected 'lon lat'", line)),
+    }
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::file_writer_poly::render_poly;
+
+    use geo::Area;
+
+    const SQUARE_WITH_HOLE: &str = "test\n\
+        area_1\n\
+        \t0.0 0.0\n\
+        \t10.0 0.0\n\
+        \t10.0 10.0\n\
+        \t0.0 10.0\n\
+        \t0.0 0.0\n\
+        END\n\
+        !hole\n\
+        \t2.0E0 2.0\n\
+        \t4.0 2.0\n\
+        \t4.0 4.0\n\
+        \t2.0 4.0\n\
+        END\n\
+        END\n";
+
+    #[test]
+    fn test_parse_poly_reads_sections_and_holes() {
+        let poly = parse_poly(SQUARE_WITH_HOLE).unwrap();
+
+        assert_eq!(poly.name, "test");
+        assert_eq!(poly.sections.len(), 2);
+        assert_eq!((poly.sections[0].is_hole, poly.sections[0].line), (false, 2));
+        assert_eq!(
+            (poly.sections[1].name.as_str(), poly.sections[1].is_hole),
+            ("hole", true)
+        );
+        assert_eq!(poly.sections[1].points[0], Point { lat: 2.0, lon: 2.0 });
+        assert!((poly.to_multi_polygon().unsigned_area() - 96.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_parse_poly_reads_written_files() {
+        let poly = parse_poly(SQUARE_WITH_HOLE).unwrap();
+        let polygon = poly.to_polygon();
+
+        let reread = parse_poly(&render_poly(&polygon)).unwrap();
+
+        assert_eq!(polygon.name, poly.name);
+        assert_eq!(reread.sections.len(), 2);
+        assert_eq!(reread.sections[1].points, poly.sections[1].points);
+        assert!(reread.sections.iter().all(|section| !section.is_hole));
+        // holes that are not marked are derived from the nesting of the rings
+        assert!((reread.to_multi_polygon().unsigned_area() - 96.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_parse_poly_reports_line_of_error() {
+        let error = |text: &str| parse_poly(text).unwrap_err().to_string();
+
+        assert_eq!(error(""), "line 1: file is empty");
+        assert_eq!(
+            error("x\n1\n\t1.0 2.0 3.0\nEND\nEND"),
+            "line 3: invalid coordinate '1.0 2.0 3.0', expected 'lon lat'"
+        );
+        assert_eq!(
+            error("x\n1\n\t1.0 north\nEND\nEND"),
+            "line 3: invalid coordinate '1.0 north'"
+        );
+        assert_eq!(
+            error("x\n1\n\t1.0 95.0\nEND\nEND"),
+            "line 3: coordinate '1.0 95.0' is out of range"
+        );
+        assert_eq!(error("x\n1\n\t1.0 2.0\nEND\n"), "line 4: missing the final END");
+        assert_eq!(
+            error("x\n1\n\t1.0 2.0\n"),
+            "line 3: section '1' starting at line 2 is missing its END"
+        );
+        assert_eq!(error("x\n1\nEND\nEND"), "line 3: section '1' has no coordinates");
+        assert_eq!(
+            error("x\n\t1.0 2.0\nEND\nEND"),
+            "line 2: expected a section name or END, found coordinates '1.0 2.0'"
+        );
+        assert_eq!(
+            error("x\nEND\nmore"),
+            "line 3: unexpected content after the final END: 'more'"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use crate::converter::Polygon;
+use crate::geometry::{self, BoundingBox};
+
+use geo::{BoundingRect, Intersects, Relate};
+use geo_types::{Coord, MultiPolygon, Rect};
+
+// the region given by --within-bbox or --within-poly that the extracted boundaries are selected by
+#[derive(Clone, Debug)]
+pub struct Region {
+    bbox: BoundingBox,
+    geometry: MultiPolygon<f64>,
+    pub fully_within: bool,
+}
+
+impl Region {
+    pub fn from_bbox(text: &str, fully_within: bool) -> Result<Region, String> {
+        let values: Vec<f64> = text
+            .split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("'{}' is not of the form minlon,minlat,maxlon,maxlat", text))?;
+
+        let bbox = match values[..] {
+            [min_lon, min_lat, max_lon, max_lat] => BoundingBox {
+                min_lon,
+                min_lat,
+                max_lon,
+                max_lat,
+            },
+            _ => return Err(format!("'{}' is not of the form minlon,minlat,maxlon,maxlat", text)),
+        };
+        if bbox.min_lon > bbox.max_lon || bbox.min_lat > bbox.max_lat {
+            return Err(format!("'{}' has a minimum bigger than the maximum", text));
+        }
+
+        let rect = Rect::new(
+            Coord {
+                x: bbox.min_lon,
+                y: bbox.min_lat,
+            },
+            Coord {
+                x: bbox.max_lon,
+                y: bbox.max_lat,
+            },
+        );
+
+        Ok(Region {
+            bbox,
+            geometry: MultiPolygon(vec![rect.to_polygon()]),
+            fully_within,
+        })
+    }
+
+    pub fn from_geometry(geometry: MultiPolygon<f64>, fully_within: bool) -> Result<Region, String> {
+        let rect = geometry
+            .bounding_rect()
+            .ok_or_else(|| String::from("the polygon has no area"))?;
+        let bbox = BoundingBox {
+            min_lon: rect.min().x,
+            min_lat: rect.min().y,
+            max_lon: rect.max().x,
+            max_lat: rect.max().y,
+        };
+
+        Ok(Region {
+            bbox,
+            geometry,
+            fully_within,
+        })
+    }
+
+    // coarse test on the extent of the nodes of a boundary, before its rings are assembled
+    pub fn may_select(&self, bbox: &BoundingBox) -> bool {
+        if self.fully_within {
+            self.bbox.covers(bbox)
+        } else {
+            self.bbox.intersects(bbox)
+        }
+    }
+
+    pub fn selects(&self, polygon: &Polygon) -> bool {
+        let boundary = geometry::split_at_antimeridian(polygon);
+        if boundary.0.is_empty() {
+            return false;
+        }
+
+        if self.fully_within {
+            // covers instead of contains, so that boundaries sharing a border with the region are inside
+            self.geometry.relate(&boundary).is_covers()
+        } else {
+            self.geometry.intersects(&boundary)
+        }
+    }
+
+    // the geometry and mode of the region as text, e.g., to tell whether two regions are the same
+    pub fn describe(&self) -> String {
+        format!("{:?} fully_within={}", self.geometry.0, self.fully_within)
+    }
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::square_ring;
+
+    fn square(min: f32, max: f32) -> Polygon {
+        Polygon {
+            points: vec![square_ring(min, max)],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_from_bbox_rejects_malformed_boxes() {
+        assert!(Region::from_bbox("1,2,3", false).is_err());
+        assert!(Region::from_bbox("1,2,3,x", false).is_err());
+        assert!(Region::from_bbox("3,2,1,4", false).is_err());
+        assert!(Region::from_bbox(" 1, 2,3,4", false).is_ok());
+    }
+
+    #[test]
+    fn test_selects_intersecting_or_fully_within() {
+        let intersecting = Region::from_bbox("0,0,10,10", false).unwrap();
+        let within = Region::from_bbox("0,0,10,10", true).unwrap();
+
+        let inside = square(2.0, 4.0);
+        let overlapping = square(8.0, 12.0);
+        let outside = square(20.0, 30.0);
+
+        assert!(intersecting.selects(&inside));
+        assert!(intersecting.selects(&overlapping));
+        assert!(!intersecting.selects(&outside));
+
+        assert!(within.selects(&inside));
+        assert!(within.selects(&square(0.0, 10.0)));
+        assert!(!within.selects(&overlapping));
+        assert!(!within.selects(&Polygon::default()));
+    }
+
+    #[test]
+    fn test_may_select_compares_extents() {
+        let region = Region::from_geometry(geometry::to_multi_polygon(&square(0.0, 10.0)), true).unwrap();
+        let bbox = |min, max| BoundingBox {
+            min_lon: min,
+            min_lat: min,
+            max_lon: max,
+            max_lat: max,
+        };
+
+        assert!(region.may_select(&bbox(1.0, 2.0)));
+        assert!(!region.may_select(&bbox(5.0, 15.0)));
+        assert!(!region.may_select(&bbox(20.0, 30.0)));
+    }
+}
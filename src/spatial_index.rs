@@ -0,0 +1,121 @@
+use crate::converter::Polygon;
+use crate::geometry::{polygon_contains, BoundingBox};
+
+use rstar::primitives::{GeomWithData, Rectangle};
+use rstar::RTree;
+
+use std::collections::BTreeSet;
+
+type RingEnvelope = GeomWithData<Rectangle<[f64; 2]>, usize>;
+
+pub struct BoundaryIndex<'a> {
+    polygons: &'a [Polygon],
+    tree: RTree<RingEnvelope>,
+}
+
+impl<'a> BoundaryIndex<'a> {
+    pub fn new(polygons: &'a [Polygon]) -> BoundaryIndex<'a> {
+        let envelopes: Vec<RingEnvelope> = polygons
+            .iter()
+            .enumerate()
+            .flat_map(|(index, polygon)| {
+                polygon
+                    .points
+                    .iter()
+                    .filter_map(|ring| BoundingBox::of_points(ring.iter()))
+                    .map(move |b| {
+                        let rectangle = Rectangle::from_corners([b.min_lon, b.min_lat], [b.max_lon, b.max_lat]);
+                        GeomWithData::new(rectangle, index)
+                    })
+            })
+            .collect();
+
+        BoundaryIndex {
+            polygons,
+            tree: RTree::bulk_load(envelopes),
+        }
+    }
+
+    pub fn lookup(&self, lon: f64, lat: f64) -> Vec<&'a Polygon> {
+        // a polygon can have several rings containing the point, hence the set
+        let candidates: BTreeSet<usize> = self
+            .tree
+            .locate_all_at_point(&[lon, lat])
+            .map(|ring| ring.data)
+            .collect();
+
+        let mut result: Vec<&'a Polygon> = candidates
+            .into_iter()
+            .map(|index| &self.polygons[index])
+            .filter(|polygon| polygon_contains(polygon, lon, lat))
+            .collect();
+
+        result.sort_by_key(|p| (p.admin_level, p.relation_id));
+        result
+    }
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::Point;
+    use crate::geometry::square_ring;
+
+    fn create_polygon(relation_id: i64, admin_level: i64, rings: Vec<Vec<Point>>) -> Polygon {
+        Polygon {
+            name: format!("polygon_{}", relation_id),
+            points: rings,
+            relation_id,
+            admin_level,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_lookup_returns_containing_polygons_sorted_by_admin_level() {
+        let polygons = vec![
+            create_polygon(3, 8, vec![square_ring(1.0, 2.0)]),
+            create_polygon(1, 2, vec![square_ring(0.0, 10.0)]),
+            create_polygon(2, 4, vec![square_ring(0.0, 5.0)]),
+        ];
+        let index = BoundaryIndex::new(&polygons);
+
+        let result: Vec<i64> = index.lookup(1.5, 1.5).iter().map(|p| p.relation_id).collect();
+
+        assert_eq!(result, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_lookup_ignores_polygon_if_point_is_only_inside_bounding_box() {
+        let triangle = vec![
+            Point { lat: 0.0, lon: 0.0 },
+            Point { lat: 0.0, lon: 10.0 },
+            Point { lat: 10.0, lon: 10.0 },
+            Point { lat: 0.0, lon: 0.0 },
+        ];
+        let polygons = vec![create_polygon(1, 8, vec![triangle])];
+        let index = BoundaryIndex::new(&polygons);
+
+        assert!(index.lookup(1.0, 9.0).is_empty());
+        assert_eq!(index.lookup(9.0, 1.0).len(), 1);
+    }
+
+    #[test]
+    fn test_lookup_respects_holes() {
+        let polygons = vec![create_polygon(
+            1,
+            8,
+            vec![square_ring(0.0, 10.0), square_ring(4.0, 6.0)],
+        )];
+        let index = BoundaryIndex::new(&polygons);
+
+        assert!(index.lookup(5.0, 5.0).is_empty());
+        assert_eq!(index.lookup(1.0, 1.0).len(), 1);
+    }
+}
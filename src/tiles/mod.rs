@@ -0,0 +1,25 @@
+pub mod tile_store;
+pub mod tiler;
+
+mod mvt_encoder;
+
+pub enum TileOutput {
+    Directory(String),
+    MbTiles(String),
+}
+
+pub struct TileConfiguration {
+    pub output: TileOutput,
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+}
+
+impl TileOutput {
+    pub fn from_path(path: &str) -> TileOutput {
+        if path.to_lowercase().ends_with(".mbtiles") {
+            TileOutput::MbTiles(path.to_string())
+        } else {
+            TileOutput::Directory(path.to_string())
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use crate::tiles::tiler::{TileFeature, TileRing};
+
+use std::collections::HashMap;
+
+pub const EXTENT: u32 = 4096;
+
+const MVT_VERSION: u32 = 2;
+const GEOM_TYPE_POLYGON: u64 = 3;
+
+const CMD_MOVE_TO: u32 = 1;
+const CMD_LINE_TO: u32 = 2;
+const CMD_CLOSE_PATH: u32 = 7;
+
+const WIRE_VARINT: u32 = 0;
+const WIRE_LENGTH_DELIMITED: u32 = 2;
+
+pub enum TileValue {
+    String(String),
+    Int(i64),
+}
+
+pub struct EncodableFeature<'a> {
+    pub feature: &'a TileFeature,
+    pub properties: Vec<(&'static str, TileValue)>,
+}
+
+pub fn encode_tile(layers: &[(String, Vec<EncodableFeature>)]) -> Vec<u8> {
+    let mut tile = Vec::new();
+    for (name, features) in layers {
+        let layer = encode_layer(name, features);
+        write_bytes_field(&mut tile, 3, &layer);
+    }
+    tile
+}
+
+fn encode_layer(name: &str, features: &[EncodableFeature]) -> Vec<u8> {
+    let mut keys: Vec<&str> = Vec::new();
+    let mut key_index: HashMap<&str, u32> = HashMap::new();
+    let mut values: Vec<Vec<u8>> = Vec::new();
+    let mut value_index: HashMap<Vec<u8>, u32> = HashMap::new();
+
+    let mut encoded_features = Vec::new();
+    for feature in features {
+        let mut tags = Vec::new();
+        for (key, value) in &feature.properties {
+            let next_key = keys.len() as u32;
+            let k = *key_index.entry(key).or_insert(next_key);
+            if k == next_key {
+                keys.push(key);
+            }
+
+            let encoded_value = encode_value(value);
+            let next_value = values.len() as u32;
+            let v = *value_index.entry(encoded_value.clone()).or_insert(next_value);
+            if v == next_value {
+                values.push(encoded_value);
+            }
+
+            tags.push(k);
+            tags.push(v);
+        }
+        encoded_features.push(encode_feature(feature.feature, &tags));
+    }
+
+    let mut layer = Vec::new();
+    write_varint_field(&mut layer, 15, MVT_VERSION as u64);
+    write_bytes_field(&mut layer, 1, name.as_bytes());
+    for feature in &encoded_features {
+        write_bytes_field(&mut layer, 2, feature);
+    }
+    for key in &keys {
+        write_bytes_field(&mut layer, 3, key.as_bytes());
+    }
+    for value in &values {
+        write_bytes_field(&mut layer, 4, value);
+    }
+    write_varint_field(&mut layer, 5, EXTENT as u64);
+    layer
+}
+
+fn encode_feature(feature: &TileFeature, tags: &[u32]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    write_varint_field(&mut buf, 1, feature.id);
+    write_packed_field(&mut buf, 2, tags);
+    write_varint_field(&mut buf, 3, GEOM_TYPE_POLYGON);
+    write_packed_field(&mut buf, 4, &encode_geometry(&feature.rings));
+    buf
+}
+
+fn encode_value(value: &TileValue) -> Vec<u8> {
+    let mut buf = Vec::new();
+    match value {
+        TileValue::String(s) => write_bytes_field(&mut buf, 1, s.as_bytes()),
+        TileValue::Int(i) => write_varint_field(&mut buf, 4, *i as u64),
+    }
+    buf
+}
+
+fn encode_geometry(rings: &[TileRing]) -> Vec<u32> {
+    /*
+        every ring is encoded as
+            MoveTo(1) dx dy, LineTo(n-1) [dx dy]*, ClosePath
+        where all coordinates are relative to the previous cursor position
+    */
+    let mut commands = Vec::new();
+    let mut cursor = (0i32, 0i32);
+
+    for ring in rings {
+        let (first, rest) = match ring.split_first() {
+            Some(x) => x,
+            None => continue,
+        };
+
+        commands.push(command(CMD_MOVE_TO, 1));
+        commands.push(zigzag(first.0 - cursor.0));
+        commands.push(zigzag(first.1 - cursor.1));
+        cursor = *first;
+
+        commands.push(command(CMD_LINE_TO, rest.len() as u32));
+        for point in rest {
+            commands.push(zigzag(point.0 - cursor.0));
+            commands.push(zigzag(point.1 - cursor.1));
+            cursor = *point;
+        }
+
+        commands.push(command(CMD_CLOSE_PATH, 1));
+    }
+
+    commands
+}
+
+fn command(id: u32, count: u32) -> u32 {
+    (id & 0x7) | (count << 3)
+}
+
+fn zigzag(n: i32) -> u32 {
+    ((n << 1) ^ (n >> 31)) as u32
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_key(buf: &mut Vec<u8>, field: u32, wire_type: u32) {
+    write_varint(buf, ((field << 3) | wire_type) as u64);
+}
+
+fn write_varint_field(buf: &mut Vec<u8>, field: u32, value: u64) {
+    write_key(buf, field, WIRE_VARINT);
+    write_varint(buf, value);
+}
+
+fn write_bytes_field(buf: &mut Vec<u8>, field: u32, bytes: &[u8]) {
+    write_key(buf, field, WIRE_LENGTH_DELIMITED);
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_packed_field(buf: &mut Vec<u8>, field: u32, values: &[u32]) {
+    let mut packed = Vec::new();
+    for value in values {
+        write_varint(&mut packed, *value as u64);
+    }
+    write_bytes_field(buf, field, &packed);
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zigzag_encodes_small_negatives_as_small_numbers() {
+        assert_eq!(zigzag(0), 0);
+        assert_eq!(zigzag(-1), 1);
+        assert_eq!(zigzag(1), 2);
+        assert_eq!(zigzag(-2), 3);
+    }
+
+    #[test]
+    fn test_write_varint_uses_continuation_bits() {
+        let mut buf = Vec::new();
+        write_varint(&mut buf, 300);
+        assert_eq!(buf, vec![0xAC, 0x02]);
+    }
+
+    #[test]
+    fn test_encode_geometry_of_square_matches_spec_example() {
+        // example taken from the vector tile specification (section 4.3.5)
+        let ring: TileRing = vec![(3, 6), (8, 12), (20, 34)];
+
+        let result = encode_geometry(&[ring]);
+
+        assert_eq!(result, vec![9, 6, 12, 18, 10, 12, 24, 44, 15]);
+    }
+
+    #[test]
+    fn test_encode_layer_deduplicates_keys_and_values() {
+        let feature = TileFeature {
+            id: 1,
+            rings: vec![vec![(0, 0), (10, 0), (10, 10)]],
+        };
+        let features = vec![
+            EncodableFeature {
+                feature: &feature,
+                properties: vec![("name", TileValue::String("a".to_string()))],
+            },
+            EncodableFeature {
+                feature: &feature,
+                properties: vec![("name", TileValue::String("a".to_string()))],
+            },
+        ];
+
+        let layer = encode_layer("admin_level_8", &features);
+
+        let occurrences = layer.windows(4).filter(|w| w == b"name").count();
+        assert_eq!(occurrences, 1);
+    }
+}
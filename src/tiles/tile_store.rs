@@ -0,0 +1,202 @@
+use crate::converter::Polygon;
+use crate::output::file_creator::FileCreator;
+use crate::tiles::mvt_encoder::{encode_tile, EncodableFeature, TileValue};
+use crate::tiles::tiler::{for_each_tile, Tile, TileId};
+use crate::tiles::{TileConfiguration, TileOutput};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rusqlite::{params, Connection};
+
+use std::collections::BTreeSet;
+use std::fs::create_dir_all;
+use std::io::prelude::*;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+trait TileSink {
+    fn write_tile(&mut self, id: TileId, data: &[u8]) -> Result<()>;
+    fn finish(&mut self, metadata: &[(&str, String)]) -> Result<()>;
+}
+
+pub fn write_tiles(polygons: &[Polygon], config: &TileConfiguration, file_creator: &mut FileCreator) -> Result<u64> {
+    let now = Instant::now();
+    println!("writing vector tiles...");
+
+    let mut sink: Box<dyn TileSink + '_> = match &config.output {
+        TileOutput::Directory(path) => Box::new(DirectorySink::new(path, file_creator)?),
+        TileOutput::MbTiles(path) => Box::new(MbTilesSink::new(path, file_creator)?),
+    };
+
+    let mut tile_count = 0;
+    for_each_tile(polygons, config.min_zoom, config.max_zoom, &mut |tile| {
+        tile_count += 1;
+        sink.write_tile(tile.id, &encode(&tile))
+    })?;
+
+    sink.finish(&create_metadata(polygons, config))?;
+
+    println!("finished writing {} tiles! {}s", tile_count, now.elapsed().as_secs());
+    Ok(tile_count)
+}
+
+pub fn layer_name(admin_level: i64) -> String {
+    format!("admin_level_{}", admin_level)
+}
+
+fn encode(tile: &Tile) -> Vec<u8> {
+    let levels: BTreeSet<i64> = tile.features.iter().map(|(p, _)| p.admin_level).collect();
+
+    let layers: Vec<(String, Vec<EncodableFeature>)> = levels
+        .into_iter()
+        .map(|level| {
+            let features = tile
+                .features
+                .iter()
+                .filter(|(p, _)| p.admin_level == level)
+                .map(|(polygon, feature)| EncodableFeature {
+                    feature,
+                    properties: vec![
+                        ("name", TileValue::String(polygon.name.clone())),
+                        ("relation_id", TileValue::Int(polygon.relation_id)),
+                        ("admin_level", TileValue::Int(polygon.admin_level)),
+                    ],
+                })
+                .collect();
+            (layer_name(level), features)
+        })
+        .collect();
+
+    encode_tile(&layers)
+}
+
+fn create_metadata(polygons: &[Polygon], config: &TileConfiguration) -> Vec<(&'static str, String)> {
+    let levels: BTreeSet<i64> = polygons.iter().map(|p| p.admin_level).collect();
+    let vector_layers: Vec<serde_json::Value> = levels
+        .iter()
+        .map(|level| {
+            serde_json::json!({
+                "id": layer_name(*level),
+                "minzoom": config.min_zoom,
+                "maxzoom": config.max_zoom,
+                "fields": {"name": "String", "relation_id": "Number", "admin_level": "Number"},
+            })
+        })
+        .collect();
+
+    vec![
+        ("name", String::from("osm_extract_polygon")),
+        ("format", String::from("pbf")),
+        ("type", String::from("overlay")),
+        ("minzoom", config.min_zoom.to_string()),
+        ("maxzoom", config.max_zoom.to_string()),
+        (
+            "json",
+            serde_json::json!({ "vector_layers": vector_layers }).to_string(),
+        ),
+    ]
+}
+
+struct DirectorySink<'a> {
+    folder: String,
+    file_creator: &'a mut FileCreator,
+}
+
+impl DirectorySink<'_> {
+    fn new<'a>(folder: &str, file_creator: &'a mut FileCreator) -> Result<DirectorySink<'a>> {
+        create_dir_all(folder)?;
+        Ok(DirectorySink {
+            folder: folder.to_string(),
+            file_creator,
+        })
+    }
+
+    fn write(&mut self, filename: &Path, data: &[u8]) -> Result<()> {
+        match self.file_creator.create_file(filename) {
+            Ok(mut file) => file.write_all(data),
+            // a skipped file keeps the tile of a previous run
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl TileSink for DirectorySink<'_> {
+    fn write_tile(&mut self, id: TileId, data: &[u8]) -> Result<()> {
+        let filename: PathBuf = [
+            self.folder.clone(),
+            id.z.to_string(),
+            id.x.to_string(),
+            format!("{}.pbf", id.y),
+        ]
+        .iter()
+        .collect();
+        self.write(&filename, data)
+    }
+
+    fn finish(&mut self, metadata: &[(&str, String)]) -> Result<()> {
+        let json: serde_json::Map<String, serde_json::Value> = metadata
+            .iter()
+            .map(|(k, v)| (k.to_string(), serde_json::Value::from(v.as_str())))
+            .collect();
+        let filename = PathBuf::from(&self.folder).join("metadata.json");
+        self.write(&filename, serde_json::Value::Object(json).to_string().as_bytes())
+    }
+}
+
+struct MbTilesSink {
+    connection: Connection,
+}
+
+impl MbTilesSink {
+    fn new(filename: &str, file_creator: &mut FileCreator) -> Result<MbTilesSink> {
+        // asks before an existing file is replaced, the database is then created in the emptied file
+        drop(file_creator.create_file(Path::new(filename))?);
+
+        let connection = Connection::open(filename).map_err(to_io_error)?;
+        connection
+            .execute_batch(
+                "CREATE TABLE metadata (name TEXT, value TEXT);
+                 CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER, tile_data BLOB);
+                 CREATE UNIQUE INDEX tile_index ON tiles (zoom_level, tile_column, tile_row);
+                 BEGIN;",
+            )
+            .map_err(to_io_error)?;
+        Ok(MbTilesSink { connection })
+    }
+}
+
+impl TileSink for MbTilesSink {
+    fn write_tile(&mut self, id: TileId, data: &[u8]) -> Result<()> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+
+        // MBTiles uses the TMS scheme, i.e., rows are counted from the bottom
+        let tms_row = (1u32 << id.z) - 1 - id.y;
+        self.connection
+            .execute(
+                "INSERT INTO tiles (zoom_level, tile_column, tile_row, tile_data) VALUES (?1, ?2, ?3, ?4)",
+                params![id.z, id.x, tms_row, compressed],
+            )
+            .map_err(to_io_error)?;
+        Ok(())
+    }
+
+    fn finish(&mut self, metadata: &[(&str, String)]) -> Result<()> {
+        for (name, value) in metadata {
+            self.connection
+                .execute(
+                    "INSERT INTO metadata (name, value) VALUES (?1, ?2)",
+                    params![name, value],
+                )
+                .map_err(to_io_error)?;
+        }
+        self.connection.execute_batch("COMMIT;").map_err(to_io_error)
+    }
+}
+
+fn to_io_error(e: rusqlite::Error) -> Error {
+    Error::other(e.to_string())
+}
@@ -0,0 +1,428 @@
+use crate::converter::Polygon;
+use crate::geometry::to_multi_polygon;
+use crate::tiles::mvt_encoder::EXTENT;
+
+use geo::Simplify;
+use geo_types::{Coord, LineString};
+
+use std::f64::consts::PI;
+use std::io::Result;
+
+pub type TileRing = Vec<(i32, i32)>;
+
+const MAX_LATITUDE: f64 = 85.051_128_78;
+const BUFFER: f64 = 64.0;
+// one pixel of a 256px tile
+const SIMPLIFY_TOLERANCE: f64 = EXTENT as f64 / 256.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TileId {
+    pub z: u8,
+    pub x: u32,
+    pub y: u32,
+}
+
+pub struct TileFeature {
+    pub id: u64,
+    pub rings: Vec<TileRing>,
+}
+
+pub struct Tile<'a> {
+    pub id: TileId,
+    pub features: Vec<(&'a Polygon, TileFeature)>,
+}
+
+// one polygon clipped to the buffered square of a tile, every part is an exterior ring followed by its holes
+struct ClippedPolygon<'a> {
+    polygon: &'a Polygon,
+    parts: Vec<Vec<Vec<(f64, f64)>>>,
+}
+
+pub fn for_each_tile<'a, F>(polygons: &'a [Polygon], min_zoom: u8, max_zoom: u8, emit: &mut F) -> Result<()>
+where
+    F: FnMut(Tile<'a>) -> Result<()>,
+{
+    let world: Vec<ClippedPolygon> = polygons.iter().filter_map(project_polygon).collect();
+    visit(TileId { z: 0, x: 0, y: 0 }, &world, min_zoom, max_zoom, emit)
+}
+
+fn visit<'a, F>(id: TileId, clipped: &[ClippedPolygon<'a>], min_zoom: u8, max_zoom: u8, emit: &mut F) -> Result<()>
+where
+    F: FnMut(Tile<'a>) -> Result<()>,
+{
+    /*
+        walks the quadtree depth first: every child only clips what is left of the
+        polygons in its parent, and tiles are handed out as soon as they are built
+    */
+    if id.z >= min_zoom {
+        let features: Vec<(&Polygon, TileFeature)> = clipped
+            .iter()
+            .filter_map(|c| to_tile_feature(c, id).map(|feature| (c.polygon, feature)))
+            .collect();
+        if !features.is_empty() {
+            emit(Tile { id, features })?;
+        }
+    }
+
+    if id.z < max_zoom {
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let child = TileId {
+                z: id.z + 1,
+                x: id.x * 2 + dx,
+                y: id.y * 2 + dy,
+            };
+            let (min, max) = buffered_bounds(child);
+            let child_clipped: Vec<ClippedPolygon> = clipped.iter().filter_map(|c| clip_polygon(c, min, max)).collect();
+            if !child_clipped.is_empty() {
+                visit(child, &child_clipped, min_zoom, max_zoom, emit)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn project_polygon(polygon: &Polygon) -> Option<ClippedPolygon<'_>> {
+    let project_ring = |ring: &LineString<f64>| ring.0.iter().map(|c| project(c.x, c.y, 1.0)).collect::<Vec<_>>();
+    let parts: Vec<Vec<Vec<(f64, f64)>>> = to_multi_polygon(polygon)
+        .0
+        .iter()
+        .map(|part| {
+            std::iter::once(part.exterior())
+                .chain(part.interiors())
+                .map(project_ring)
+                .collect::<Vec<_>>()
+        })
+        .filter(|rings| rings[0].len() >= 3)
+        .map(|rings| rings.into_iter().filter(|ring| ring.len() >= 3).collect())
+        .collect();
+
+    if parts.is_empty() {
+        return None;
+    }
+    Some(ClippedPolygon { polygon, parts })
+}
+
+// the square of a tile including its buffer, in world coordinates from 0 to 1
+fn buffered_bounds(id: TileId) -> ((f64, f64), (f64, f64)) {
+    let size = 1.0 / (1u64 << id.z) as f64;
+    let buffer = BUFFER / EXTENT as f64 * size;
+    let origin = (id.x as f64 * size, id.y as f64 * size);
+    (
+        (origin.0 - buffer, origin.1 - buffer),
+        (origin.0 + size + buffer, origin.1 + size + buffer),
+    )
+}
+
+fn clip_polygon<'a>(clipped: &ClippedPolygon<'a>, min: (f64, f64), max: (f64, f64)) -> Option<ClippedPolygon<'a>> {
+    let parts: Vec<Vec<Vec<(f64, f64)>>> = clipped
+        .parts
+        .iter()
+        .filter_map(|rings| {
+            // the holes of an exterior ring that vanishes in this tile are dropped with it
+            let exterior = clip_ring(&rings[0], min, max);
+            if exterior.len() < 3 {
+                return None;
+            }
+            Some(
+                std::iter::once(exterior)
+                    .chain(
+                        rings[1..]
+                            .iter()
+                            .map(|ring| clip_ring(ring, min, max))
+                            .filter(|ring| ring.len() >= 3),
+                    )
+                    .collect(),
+            )
+        })
+        .collect();
+
+    if parts.is_empty() {
+        return None;
+    }
+    Some(ClippedPolygon {
+        polygon: clipped.polygon,
+        parts,
+    })
+}
+
+fn to_tile_feature(clipped: &ClippedPolygon, id: TileId) -> Option<TileFeature> {
+    let scale = (1u64 << id.z) as f64 * EXTENT as f64;
+    let origin = (id.x as f64 * EXTENT as f64, id.y as f64 * EXTENT as f64);
+    let to_pixels =
+        |ring: &Vec<(f64, f64)>| simplify_ring(&ring.iter().map(|&(x, y)| (x * scale, y * scale)).collect::<Vec<_>>());
+
+    let mut tile_rings: Vec<TileRing> = Vec::new();
+    for rings in &clipped.parts {
+        if let Some(exterior) = to_tile_ring(&to_pixels(&rings[0]), origin, true) {
+            tile_rings.push(exterior);
+            tile_rings.extend(
+                rings[1..]
+                    .iter()
+                    .filter_map(|ring| to_tile_ring(&to_pixels(ring), origin, false)),
+            );
+        }
+    }
+
+    if tile_rings.is_empty() {
+        return None;
+    }
+    Some(TileFeature {
+        id: clipped.polygon.relation_id as u64,
+        rings: tile_rings,
+    })
+}
+
+fn project(lon: f64, lat: f64, world_size: f64) -> (f64, f64) {
+    let lat = lat.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    let x = (lon + 180.0) / 360.0;
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0;
+    (x * world_size, y * world_size)
+}
+
+fn simplify_ring(ring: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let linestring = LineString(ring.iter().map(|&(x, y)| Coord { x, y }).collect());
+    linestring
+        .simplify(SIMPLIFY_TOLERANCE)
+        .0
+        .into_iter()
+        .map(|c| (c.x, c.y))
+        .collect()
+}
+
+fn clip_ring(ring: &[(f64, f64)], min: (f64, f64), max: (f64, f64)) -> Vec<(f64, f64)> {
+    /*
+        Sutherland-Hodgman clipping against the buffered tile square,
+        one edge of the square at a time
+    */
+    let mut output = ring.to_vec();
+    output = clip_edge(&output, |p| p.0 >= min.0, |a, b| intersect_x(a, b, min.0));
+    output = clip_edge(&output, |p| p.0 <= max.0, |a, b| intersect_x(a, b, max.0));
+    output = clip_edge(&output, |p| p.1 >= min.1, |a, b| intersect_y(a, b, min.1));
+    output = clip_edge(&output, |p| p.1 <= max.1, |a, b| intersect_y(a, b, max.1));
+    output
+}
+
+fn clip_edge<I, S>(ring: &[(f64, f64)], inside: I, intersect: S) -> Vec<(f64, f64)>
+where
+    I: Fn(&(f64, f64)) -> bool,
+    S: Fn(&(f64, f64), &(f64, f64)) -> (f64, f64),
+{
+    let mut output = Vec::with_capacity(ring.len());
+    let mut previous = match ring.last() {
+        Some(p) => p,
+        None => return output,
+    };
+
+    for current in ring {
+        match (inside(previous), inside(current)) {
+            (true, true) => output.push(*current),
+            (true, false) => output.push(intersect(previous, current)),
+            (false, true) => {
+                output.push(intersect(previous, current));
+                output.push(*current);
+            }
+            (false, false) => {}
+        }
+        previous = current;
+    }
+    output
+}
+
+fn intersect_x(a: &(f64, f64), b: &(f64, f64), x: f64) -> (f64, f64) {
+    let t = (x - a.0) / (b.0 - a.0);
+    (x, a.1 + t * (b.1 - a.1))
+}
+
+fn intersect_y(a: &(f64, f64), b: &(f64, f64), y: f64) -> (f64, f64) {
+    let t = (y - a.1) / (b.1 - a.1);
+    (a.0 + t * (b.0 - a.0), y)
+}
+
+fn to_tile_ring(ring: &[(f64, f64)], origin: (f64, f64), is_exterior: bool) -> Option<TileRing> {
+    let mut tile_ring: TileRing = Vec::with_capacity(ring.len());
+    for &(x, y) in ring {
+        let p = ((x - origin.0).round() as i32, (y - origin.1).round() as i32);
+        if tile_ring.last() != Some(&p) {
+            tile_ring.push(p);
+        }
+    }
+    while tile_ring.len() > 1 && tile_ring.first() == tile_ring.last() {
+        tile_ring.pop();
+    }
+
+    let area = signed_area(&tile_ring);
+    if tile_ring.len() < 3 || area == 0 {
+        return None;
+    }
+    // exterior rings have to be clockwise in screen coordinates, i.e., positive area, interior rings the opposite
+    if (area > 0) != is_exterior {
+        tile_ring.reverse();
+    }
+    Some(tile_ring)
+}
+
+fn signed_area(ring: &[(i32, i32)]) -> i64 {
+    let mut area = 0i64;
+    for i in 0..ring.len() {
+        let (x1, y1) = ring[i];
+        let (x2, y2) = ring[(i + 1) % ring.len()];
+        area += x1 as i64 * y2 as i64 - x2 as i64 * y1 as i64;
+    }
+    area
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::converter::Point;
+
+    fn create_polygon(ring: Vec<(f32, f32)>) -> Polygon {
+        Polygon {
+            name: "barfoo".to_string(),
+            points: vec![ring.into_iter().map(|(lon, lat)| Point { lat, lon }).collect()],
+            relation_id: 42,
+            admin_level: 8,
+            ..Default::default()
+        }
+    }
+
+    fn tiles_for_zoom(polygons: &[Polygon], zoom: u8) -> Vec<Tile<'_>> {
+        let mut tiles = Vec::new();
+        for_each_tile(polygons, zoom, zoom, &mut |tile| {
+            tiles.push(tile);
+            Ok(())
+        })
+        .unwrap();
+        tiles
+    }
+
+    #[test]
+    fn test_project_maps_origin_to_center_of_world() {
+        let (x, y) = project(0.0, 0.0, 2.0);
+        assert!((x - 1.0).abs() < 1e-9);
+        assert!((y - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_clip_ring_keeps_ring_inside_tile() {
+        let ring = vec![(10.0, 10.0), (100.0, 10.0), (100.0, 100.0)];
+        let result = clip_ring(
+            &ring,
+            (-BUFFER, -BUFFER),
+            (EXTENT as f64 + BUFFER, EXTENT as f64 + BUFFER),
+        );
+        assert_eq!(result, ring);
+    }
+
+    #[test]
+    fn test_clip_ring_cuts_ring_at_buffered_tile_border() {
+        let ring = vec![(-1000.0, 10.0), (100.0, 10.0), (100.0, 100.0), (-1000.0, 100.0)];
+        let result = clip_ring(
+            &ring,
+            (-BUFFER, -BUFFER),
+            (EXTENT as f64 + BUFFER, EXTENT as f64 + BUFFER),
+        );
+        assert!(result.iter().all(|p| p.0 >= -BUFFER));
+        assert!(result.contains(&(-BUFFER, 10.0)));
+    }
+
+    #[test]
+    fn test_to_tile_ring_makes_rings_clockwise() {
+        let counter_clockwise = vec![(0.0, 0.0), (0.0, 10.0), (10.0, 10.0), (10.0, 0.0)];
+        let result = to_tile_ring(&counter_clockwise, (0.0, 0.0), true).unwrap();
+        assert!(signed_area(&result) > 0);
+    }
+
+    #[test]
+    fn test_tiles_for_zoom_keeps_holes_as_counter_clockwise_rings() {
+        let mut polygon = create_polygon(vec![
+            (-40.0, -40.0),
+            (40.0, -40.0),
+            (40.0, 40.0),
+            (-40.0, 40.0),
+            (-40.0, -40.0),
+        ]);
+        polygon.points.push(
+            vec![
+                (-10.0, -10.0),
+                (-10.0, 10.0),
+                (10.0, 10.0),
+                (10.0, -10.0),
+                (-10.0, -10.0),
+            ]
+            .into_iter()
+            .map(|(lon, lat)| Point { lat, lon })
+            .collect(),
+        );
+        let polygons = vec![polygon];
+
+        let result = tiles_for_zoom(&polygons, 0);
+
+        let rings = &result[0].features[0].1.rings;
+        assert_eq!(rings.len(), 2);
+        assert!(signed_area(&rings[0]) > 0);
+        assert!(signed_area(&rings[1]) < 0);
+    }
+
+    #[test]
+    fn test_tiles_for_zoom_zero_results_in_single_tile() {
+        let polygon = create_polygon(vec![(8.0, 40.0), (28.0, 40.0), (28.0, 55.0), (8.0, 55.0), (8.0, 40.0)]);
+        let polygons = vec![polygon];
+
+        let result = tiles_for_zoom(&polygons, 0);
+
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].id, TileId { z: 0, x: 0, y: 0 });
+    }
+
+    #[test]
+    fn test_tiles_for_zoom_drops_polygons_smaller_than_a_pixel() {
+        let polygon = create_polygon(vec![(8.0, 49.0), (8.1, 49.0), (8.1, 49.1), (8.0, 49.1), (8.0, 49.0)]);
+        let polygons = vec![polygon];
+
+        let result = tiles_for_zoom(&polygons, 0);
+
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn test_tiles_for_zoom_splits_polygon_crossing_tile_borders() {
+        let polygon = create_polygon(vec![(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0)]);
+        let polygons = vec![polygon];
+
+        let result = tiles_for_zoom(&polygons, 1);
+
+        assert_eq!(result.len(), 4);
+    }
+
+    #[test]
+    fn test_for_each_tile_descends_only_into_tiles_reached_by_the_polygon() {
+        let polygon = create_polygon(vec![
+            (8.01, 49.01),
+            (8.02, 49.01),
+            (8.02, 49.02),
+            (8.01, 49.02),
+            (8.01, 49.01),
+        ]);
+        let polygons = vec![polygon];
+
+        let mut tiles = Vec::new();
+        for_each_tile(&polygons, 10, 15, &mut |tile| {
+            tiles.push(tile.id);
+            Ok(())
+        })
+        .unwrap();
+
+        assert!(tiles.iter().all(|id| id.z >= 10));
+        for zoom in 10..=15 {
+            let count = tiles.iter().filter(|id| id.z == zoom).count();
+            assert!((1..=9).contains(&count));
+        }
+    }
+}
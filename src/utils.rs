@@ -7,3 +7,67 @@ where
 {
     map.values().flat_map(|v| v.iter().cloned()).collect()
 }
+
+pub fn matches_glob(pattern: &str, text: &str) -> bool {
+    /*
+        supports '*' (any sequence of characters) and '?' (a single character);
+        backtracking only ever needs to resume after the last '*'
+    */
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut last_star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            last_star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = last_star {
+            p = star_p + 1;
+            t = star_t + 1;
+            last_star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_glob_without_wildcards_requires_equality() {
+        assert!(matches_glob("wikidata", "wikidata"));
+        assert!(!matches_glob("wikidata", "wikipedia"));
+        assert!(!matches_glob("name", "name:en"));
+    }
+
+    #[test]
+    fn test_matches_glob_with_star() {
+        assert!(matches_glob("name:*", "name:en"));
+        assert!(matches_glob("name:*", "name:"));
+        assert!(!matches_glob("name:*", "name"));
+        assert!(matches_glob("*", "anything"));
+        assert!(matches_glob("ISO3166-*", "ISO3166-2"));
+        assert!(matches_glob("*:*:*", "name:prefix:de"));
+    }
+
+    #[test]
+    fn test_matches_glob_with_question_mark() {
+        assert!(matches_glob("name:??", "name:en"));
+        assert!(!matches_glob("name:??", "name:ang"));
+    }
+}
@@ -0,0 +1,262 @@
+use crate::converter::Point;
+use crate::output::file_creator::FileCreator;
+use crate::poly_reader::{read_poly, PolyFile, PolySection};
+
+use geo::line_intersection::{line_intersection, LineIntersection};
+use geo_types::{Coord, Line};
+use geojson::{Feature, FeatureCollection, Geometry};
+use rstar::primitives::{GeomWithData, Rectangle};
+use rstar::{RTree, AABB};
+use serde_json::{json, Map};
+
+use std::io::prelude::*;
+use std::path::Path;
+
+// a ring with thousands of self-intersections is not made more useful by listing all of them
+const MAX_REPORTED_INTERSECTIONS: usize = 5;
+
+type SegmentEnvelope = GeomWithData<Rectangle<[f64; 2]>, usize>;
+
+#[derive(Debug, PartialEq)]
+enum Problem {
+    Error(String),
+    Warning(String),
+}
+
+// returns whether all files are valid
+pub fn run(filenames: &[String], geojson_output: bool, file_creator: &mut FileCreator) -> bool {
+    let mut all_valid = true;
+
+    for filename in filenames {
+        let poly = match read_poly(filename) {
+            Ok(poly) => poly,
+            Err(e) => {
+                println!("error: {}", e);
+                all_valid = false;
+                continue;
+            }
+        };
+
+        let problems = validate(&poly);
+        let errors = problems.iter().filter(|p| matches!(p, Problem::Error(_))).count();
+        if problems.is_empty() {
+            println!("{}: ok", filename);
+        } else {
+            println!("{}: {} errors, {} warnings", filename, errors, problems.len() - errors);
+        }
+        for problem in &problems {
+            match problem {
+                Problem::Error(message) => println!("  error: {}", message),
+                Problem::Warning(message) => println!("  warning: {}", message),
+            }
+        }
+        all_valid &= errors == 0;
+
+        if geojson_output {
+            let geojson_filename = Path::new(filename).with_extension("geojson");
+            let result = file_creator
+                .create_file(&geojson_filename)
+                .and_then(|mut file| file.write_all(to_geojson(&poly).to_string().as_bytes()));
+            match result {
+                Ok(_) => println!("{}: successfully written", geojson_filename.display()),
+                Err(e) => println!("{}: {}", geojson_filename.display(), e),
+            }
+        }
+    }
+
+    all_valid
+}
+
+fn validate(poly: &PolyFile) -> Vec<Problem> {
+    poly.sections.iter().flat_map(validate_section).collect()
+}
+
+fn validate_section(section: &PolySection) -> Vec<Problem> {
+    let description = format!(
+        "{}section '{}' (line {})",
+        if section.is_hole { "hole " } else { "" },
+        section.name,
+        section.line
+    );
+    let ring = without_consecutive_duplicates(&section.points);
+
+    let mut problems = Vec::new();
+    if !is_closed(&section.points) {
+        problems.push(Problem::Warning(format!(
+            "{} is not closed, the first and last coordinate differ",
+            description
+        )));
+    }
+
+    let distinct = if is_closed(&ring) { ring.len() - 1 } else { ring.len() };
+    if distinct < 3 {
+        problems.push(Problem::Error(format!(
+            "{} has fewer than 3 distinct coordinates",
+            description
+        )));
+        return problems;
+    }
+
+    let intersections = self_intersections(&ring);
+    if !intersections.is_empty() {
+        let mut locations: Vec<String> = intersections
+            .iter()
+            .take(MAX_REPORTED_INTERSECTIONS)
+            .map(|c| format!("({} {})", c.x, c.y))
+            .collect();
+        if intersections.len() > MAX_REPORTED_INTERSECTIONS {
+            locations.push(format!("and {} more", intersections.len() - MAX_REPORTED_INTERSECTIONS));
+        }
+        problems.push(Problem::Error(format!(
+            "{} intersects itself at {}",
+            description,
+            locations.join(", ")
+        )));
+    }
+
+    problems
+}
+
+fn is_closed(ring: &[Point]) -> bool {
+    ring.len() > 1 && ring.first() == ring.last()
+}
+
+fn without_consecutive_duplicates(ring: &[Point]) -> Vec<Point> {
+    let mut result: Vec<Point> = ring.to_vec();
+    result.dedup();
+    result
+}
+
+fn self_intersections(ring: &[Point]) -> Vec<Coord<f64>> {
+    /*
+        the ring is treated as closed; every segment is only compared with the segments whose
+        bounding boxes overlap with its own, and neighbouring segments may only share their common vertex
+    */
+    let coordinate = |p: &Point| Coord {
+        x: p.lon as f64,
+        y: p.lat as f64,
+    };
+    let mut coordinates: Vec<Coord<f64>> = ring.iter().map(coordinate).collect();
+    if !is_closed(ring) {
+        coordinates.push(coordinates[0]);
+    }
+
+    let segments: Vec<Line<f64>> = coordinates.windows(2).map(|w| Line::new(w[0], w[1])).collect();
+    let n = segments.len();
+    let envelopes: Vec<SegmentEnvelope> = segments
+        .iter()
+        .enumerate()
+        .map(|(index, s)| {
+            GeomWithData::new(
+                Rectangle::from_corners([s.start.x, s.start.y], [s.end.x, s.end.y]),
+                index,
+            )
+        })
+        .collect();
+    let tree = RTree::bulk_load(envelopes);
+
+    let mut intersections = Vec::new();
+    for (i, segment) in segments.iter().enumerate() {
+        let envelope = AABB::from_corners([segment.start.x, segment.start.y], [segment.end.x, segment.end.y]);
+        let mut candidates: Vec<usize> = tree
+            .locate_in_envelope_intersecting(&envelope)
+            .map(|e| e.data)
+            .filter(|j| *j > i)
+            .collect();
+        candidates.sort_unstable();
+
+        for j in candidates {
+            let is_neighbour = j == i + 1 || (i == 0 && j == n - 1);
+            match line_intersection(*segment, segments[j]) {
+                Some(LineIntersection::SinglePoint { .. }) if is_neighbour => {}
+                Some(LineIntersection::SinglePoint { intersection, .. }) => intersections.push(intersection),
+                Some(LineIntersection::Collinear { intersection }) => intersections.push(intersection.start),
+                None => {}
+            }
+        }
+    }
+
+    intersections
+}
+
+fn to_geojson(poly: &PolyFile) -> FeatureCollection {
+    let mut properties = Map::new();
+    properties.insert(String::from("name"), json!(poly.name));
+
+    FeatureCollection {
+        bbox: None,
+        features: vec![Feature {
+            bbox: None,
+            geometry: Some(Geometry::new(geojson::Value::from(&poly.to_multi_polygon()))),
+            id: None,
+            properties: Some(properties),
+            foreign_members: None,
+        }],
+        foreign_members: None,
+    }
+}
+
+// ////////////////////////////////////
+// ////////////////////////////////////
+// UNIT TESTS
+// ////////////////////////////////////
+// ////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(coordinates: Vec<(f32, f32)>) -> PolySection {
+        PolySection {
+            name: String::from("1"),
+            is_hole: false,
+            line: 2,
+            points: coordinates.into_iter().map(|(lon, lat)| Point { lat, lon }).collect(),
+        }
+    }
+
+    #[test]
+    fn test_valid_ring_has_no_problems() {
+        let square = section(vec![
+            (0.0, 0.0),
+            (1.0, 0.0),
+            (1.0, 1.0),
+            (1.0, 1.0),
+            (0.0, 1.0),
+            (0.0, 0.0),
+        ]);
+
+        assert_eq!(validate_section(&square), vec![]);
+    }
+
+    #[test]
+    fn test_open_ring_is_a_warning() {
+        let open = section(vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+
+        assert_eq!(
+            validate_section(&open),
+            vec![Problem::Warning(String::from(
+                "section '1' (line 2) is not closed, the first and last coordinate differ"
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_self_intersections_and_degenerate_rings_are_errors() {
+        let bowtie = section(vec![(0.0, 0.0), (2.0, 2.0), (2.0, 0.0), (0.0, 2.0), (0.0, 0.0)]);
+        let line = section(vec![(0.0, 0.0), (1.0, 1.0), (0.0, 0.0)]);
+
+        assert_eq!(
+            validate_section(&bowtie),
+            vec![Problem::Error(String::from(
+                "section '1' (line 2) intersects itself at (1 1)"
+            ))]
+        );
+        assert_eq!(
+            validate_section(&line),
+            vec![Problem::Error(String::from(
+                "section '1' (line 2) has fewer than 3 distinct coordinates"
+            ))]
+        );
+    }
+}